@@ -14,7 +14,11 @@ use SmolECS::{
 };
 use std::collections::HashSet;
 use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
 use std::io::Cursor;
+use std::io::{BufReader, BufWriter};
+use std::fs::File;
+use serde::{Serialize, Deserialize};
 
 #[derive(Copy, Clone)]
 pub struct Player;
@@ -52,28 +56,435 @@ pub struct Radius(f32);
 #[derive(Copy, Clone)]
 pub struct Health(isize);
 
+// Index of a ship, also the owner of its bullets
+#[derive(Copy, Clone)]
+pub struct Ship(usize);
+
+// The ship a projectile was fired by, credited with whatever it hits
+#[derive(Copy, Clone)]
+pub struct Owner(usize);
+
+// Points earned this round, kills in quick succession build up a combo multiplier
+#[derive(Copy, Clone)]
+pub struct Score{
+    points: u64,
+    combo: u32,
+    // Seconds left to make another kill before the combo drops back to 1
+    combo_timer: f32,
+}
+
+impl Score{
+    fn new() -> Self{
+        Score{
+            points: 0,
+            combo: 1,
+            combo_timer: 0.0,
+        }
+    }
+}
+
+// Time and distance a projectile has left before it expires
+#[derive(Copy, Clone)]
+pub struct Lifetime{
+    seconds: f32,
+    distance: f32,
+}
+
+// Every shot spends energy, a weapon with no regen and a whole number cost works as an ammo count
+#[derive(Copy, Clone)]
+pub struct Weapon{
+    // Seconds between shots while the trigger is held, and until the next shot is allowed
+    cooldown: f32,
+    ready_in: f32,
+    shot_cost: f32,
+    energy: f32,
+    max_energy: f32,
+    // Energy regained per second
+    energy_regen: f32,
+    // Projectile speed on top of the ship's own velocity
+    speed: f32,
+    // Distance ahead of the ship a projectile appears
+    spawn_offset: f32,
+    // How long and how far this weapon's projectiles fly
+    lifetime: f32,
+    range: f32,
+}
+
+impl Weapon{
+    // Advances the cooldown and energy regen by one tick, then fires if the trigger is held and the weapon is able
+    fn update(&mut self, delta: f32, trigger: bool) -> bool{
+        self.ready_in = (self.ready_in - delta).max(0.0);
+        self.energy = (self.energy + self.energy_regen * delta).min(self.max_energy);
+
+        if trigger && self.ready_in <= 0.0 && self.energy >= self.shot_cost{
+            self.ready_in = self.cooldown;
+            self.energy -= self.shot_cost;
+            return true;
+        }
+        false
+    }
+
+    // direction is the unit vector the ship is facing
+    fn shot(&self, owner: usize, position: &Position, vel: &Velocity, direction: (f32, f32)) -> Shot{
+        Shot{
+            position: Position{
+                x: position.x + direction.0 * self.spawn_offset,
+                y: position.y + direction.1 * self.spawn_offset,
+            },
+            velocity: Velocity{
+                x: vel.x + direction.0 * self.speed,
+                y: vel.y + direction.1 * self.speed,
+            },
+            owner,
+            lifetime: Lifetime{
+                seconds: self.lifetime,
+                distance: self.range,
+            },
+        }
+    }
+}
+
+impl Default for Weapon{
+    fn default() -> Self{
+        Weapon{
+            cooldown: 0.25,
+            ready_in: 0.0,
+            shot_cost: 1.0,
+            energy: 5.0,
+            max_energy: 5.0,
+            energy_regen: 2.0,
+            speed: 10.0,
+            spawn_offset: 1.0,
+            lifetime: 2.0,
+            range: 16.0,
+        }
+    }
+}
+
+// Lives left counting the current one, a ship destroyed on its last life is gone for the round
+#[derive(Copy, Clone)]
+pub struct Lives(u32);
+
+// A ship between lives, out of play with no Position until the delay is up and a safe spot is found
+#[derive(Copy, Clone)]
+pub struct Respawn{
+    seconds: f32,
+}
+
+// A freshly respawned ship takes no damage until this runs out
+#[derive(Copy, Clone)]
+pub struct Invulnerable{
+    seconds: f32,
+}
+
+// Marks an entity for removal at the end of the tick
+#[derive(Copy, Clone)]
+pub struct Dead;
+
+// Every component type, registered with the world and stripped from an entity when it despawns
+macro_rules! components{
+    ($($component:ty),* $(,)?) => {
+        fn register_components(world: &mut World){
+            $(world.register_comp::<$component>();)*
+        }
+
+        // Strips every component from entities marked Dead, then deletes them
+        fn despawn_dead(world: &World){
+            let mut dead = Vec::new();
+            {
+                let ents = Read::<EntityStorage>::get_data(world);
+                let deads = ReadComp::<Dead>::get_data(world);
+                for (marker, entity) in (&deads, ents.deref()).join(){
+                    dead.push(entity.clone());
+                }
+            }
+            if dead.is_empty(){
+                return;
+            }
+
+            $({
+                let mut storage = WriteComp::<$component>::get_data(world);
+                for entity in dead.iter(){
+                    entity.remove(&mut storage);
+                }
+            })*
+
+            let mut ents = Write::<EntityStorage>::get_data(world);
+            for entity in dead.iter(){
+                ents.delete_entity(entity);
+            }
+        }
+    };
+}
+
+components!(
+    Player,
+    Enemy,
+    Asteroid,
+    Bullet,
+    Velocity,
+    Position,
+    Rotation,
+    RotationVelocity,
+    Radius,
+    Health,
+    Collider,
+    GridIndex,
+    Lifetime,
+    Weapon,
+    Ship,
+    Controller,
+    Actions,
+    AiState,
+    Lives,
+    Owner,
+    Score,
+    Respawn,
+    Invulnerable,
+    Dead,
+);
+
+// Ship indices, carried by a ship's collider and as the Owner of its bullets
+const PLAYER_SHIP: usize = 0;
+const ENEMY_SHIP: usize = 1;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Layer{
+    Ship = 0,
+    Bullet = 1,
+    Asteroid = 2,
+}
+const LAYER_COUNT: usize = 3;
+
+impl Layer{
+    fn bit(self) -> u32{
+        1 << self as u32
+    }
+}
+
+const ALL_LAYERS: u32 = (1 << LAYER_COUNT) - 1;
+
+#[derive(Copy, Clone)]
+pub struct Collider{
+    layer: Layer,
+    // Layers this collider takes hits from
+    mask: u32,
+    // The ship itself on a ship's collider, projectiles carry an Owner component instead
+    owner: Option<usize>,
+}
+
+impl Collider{
+    fn ship(owner: usize) -> Self{
+        Collider{layer: Layer::Ship, mask: ALL_LAYERS, owner: Some(owner)}
+    }
+
+    fn bullet() -> Self{
+        Collider{layer: Layer::Bullet, mask: ALL_LAYERS, owner: None}
+    }
+
+    fn asteroid() -> Self{
+        Collider{layer: Layer::Asteroid, mask: Layer::Ship.bit() | Layer::Bullet.bit(), owner: None}
+    }
+}
+
+// How much health a hit from one layer takes off another
+pub struct DamageTable{
+    // Indexed [attacker][victim]
+    damage: [[isize; LAYER_COUNT]; LAYER_COUNT],
+    // Between a ship and its own bullets
+    own_shooter: isize,
+}
+
+impl DamageTable{
+    fn set(&mut self, attacker: Layer, victim: Layer, damage: isize){
+        self.damage[attacker as usize][victim as usize] = damage;
+    }
+
+    // Owners are the ship each side belongs to, either the ship itself or whoever fired it
+    fn damage(&self, attacker: &Collider, attacker_owner: Option<usize>, victim: &Collider, victim_owner: Option<usize>) -> isize{
+        if attacker_owner.is_some() && attacker_owner == victim_owner && attacker.layer != victim.layer{
+            return self.own_shooter;
+        }
+        self.damage[attacker.layer as usize][victim.layer as usize]
+    }
+}
+
+impl Default for DamageTable{
+    fn default() -> Self{
+        let mut table = DamageTable{
+            damage: [[0; LAYER_COUNT]; LAYER_COUNT],
+            own_shooter: 0,
+        };
+        table.set(Layer::Ship, Layer::Ship, 1);
+        table.set(Layer::Ship, Layer::Bullet, 1);
+        table.set(Layer::Ship, Layer::Asteroid, 1);
+        table.set(Layer::Bullet, Layer::Ship, 1);
+        table.set(Layer::Bullet, Layer::Bullet, 1);
+        table.set(Layer::Bullet, Layer::Asteroid, 1);
+        table.set(Layer::Asteroid, Layer::Ship, 2);
+        table.set(Layer::Asteroid, Layer::Bullet, 1);
+        table.set(Layer::Asteroid, Layer::Asteroid, 0);
+        table
+    }
+}
+
+// Room left past the visible edge so an entity is fully off screen before it wraps, the largest radius in play
+const ENTITY_MARGIN: f32 = 0.5;
+
+// Half extents of the visible arena, it wraps around a margin past these
 pub struct WorldBounds{
     x: f32, 
-    y: f32
+    y: f32,
+    margin: f32,
+}
+
+impl WorldBounds{
+    fn new(x: f32, y: f32) -> Self{
+        WorldBounds{x, y, margin: ENTITY_MARGIN}
+    }
+
+    fn width(&self) -> f32{
+        2.0 * (self.x + self.margin)
+    }
+
+    fn height(&self) -> f32{
+        2.0 * (self.y + self.margin)
+    }
+
+    fn wrap(&self, position: &mut Position){
+        if position.x < -(self.x + self.margin){
+            position.x += self.width();
+        } else if position.x > self.x + self.margin{
+            position.x -= self.width();
+        }
+
+        if position.y < -(self.y + self.margin){
+            position.y += self.height();
+        } else if position.y > self.y + self.margin{
+            position.y -= self.height();
+        }
+    }
+
+    // Shortest offset from one position to another, which may cross a wrapping edge
+    fn offset(&self, from: &Position, to: &Position) -> (f32, f32){
+        let mut x = to.x - from.x;
+        let mut y = to.y - from.y;
+
+        if x > self.width() / 2.0{
+            x -= self.width();
+        } else if x < -self.width() / 2.0{
+            x += self.width();
+        }
+
+        if y > self.height() / 2.0{
+            y -= self.height();
+        } else if y < -self.height() / 2.0{
+            y += self.height();
+        }
+        (x, y)
+    }
+
+    // Orthographic projection showing exactly the visible arena
+    fn projection(&self) -> [[f32; 4]; 4]{
+        [
+            [1.0 / self.x, 0.0, 0.0, 0.0],
+            [0.0, 1.0 / self.y, 0.0, 0.0],
+            [0.0, 0.0, -0.1, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]
+    }
 }
 
+// Simulation ticks per second
+const TICK_RATE: f64 = 60.0;
+// Longest wall-clock frame fed into the accumulator, stops a stall from queueing up hundreds of ticks
+const MAX_FRAME_TIME: f64 = 0.25;
+
 pub struct Time{
-    beginning: std::time::Instant,
     last: std::time::Instant,
+    accumulator: f64,
     total: f64,
-    delta: f64
+    delta: f64,
+    tick: u64,
+    alpha: f64,
+}
+
+impl Time{
+    fn new(tick_rate: f64) -> Self{
+        Time{
+            last: std::time::Instant::now(),
+            accumulator: 0.0,
+            total: 0.0,
+            delta: 1.0 / tick_rate,
+            tick: 0,
+            alpha: 0.0,
+        }
+    }
+
+    // Adds the wall-clock time since the last frame to the accumulator and returns how many fixed ticks are due.
+    // Whatever is left over becomes the interpolation alpha for rendering.
+    fn advance_frame(&mut self) -> u32{
+        let current = std::time::Instant::now();
+        self.accumulator += current.duration_since(self.last).as_secs_f64().min(MAX_FRAME_TIME);
+        self.last = current;
+
+        let mut ticks = 0;
+        while self.accumulator >= self.delta{
+            self.accumulator -= self.delta;
+            ticks += 1;
+        }
+        self.alpha = self.accumulator / self.delta;
+        ticks
+    }
+}
+
+// Every random draw in the game goes through this so a seed reproduces a whole match.
+// ChaCha8 is a named algorithm with a fixed output stream, unlike StdRng which may change between rand releases.
+pub struct GameRng{
+    seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl GameRng{
+    fn new(seed: u64) -> Self{
+        GameRng{
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl std::ops::Deref for GameRng{
+    type Target = ChaCha8Rng;
+
+    fn deref(&self) -> &ChaCha8Rng{
+        &self.rng
+    }
+}
+
+impl std::ops::DerefMut for GameRng{
+    fn deref_mut(&mut self) -> &mut ChaCha8Rng{
+        &mut self.rng
+    }
 }
 
 pub struct ControlInputs{
+    // Keys physically down right now, kept up to date by window events
+    held: HashSet<VirtualKeyCode>,
+    // Keys that went down since the last tick, even if they have already come back up
+    tapped: HashSet<VirtualKeyCode>,
+    // Key states as of the current simulation tick
     pressed: HashSet<VirtualKeyCode>,
-    down: HashSet<VirtualKeyCode>
+    down: HashSet<VirtualKeyCode>,
+    released: HashSet<VirtualKeyCode>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 enum KeyStatus{
     Up,
     Pressed,
     Down,
+    Released,
 }
 
 impl std::fmt::Display for KeyStatus{
@@ -83,11 +494,31 @@ impl std::fmt::Display for KeyStatus{
             KeyStatus::Up => {write!(f, "{:?}", self)}
             KeyStatus::Pressed => {write!(f, "{:?}", self)}
             KeyStatus::Down => {write!(f, "{:?}", self)}
+            KeyStatus::Released => {write!(f, "{:?}", self)}
+        }
+    }
+}
+
+impl KeyStatus{
+    fn is_held(&self) -> bool{
+        match self{
+            KeyStatus::Pressed | KeyStatus::Down => true,
+            KeyStatus::Up | KeyStatus::Released => false,
         }
     }
 }
 
 impl ControlInputs{
+    fn new() -> Self{
+        ControlInputs{
+            held: HashSet::new(),
+            tapped: HashSet::new(),
+            pressed: HashSet::new(),
+            down: HashSet::new(),
+            released: HashSet::new(),
+        }
+    }
+
     fn key_status(&self, code: VirtualKeyCode) -> KeyStatus{
         if self.pressed.contains(&code){
             return KeyStatus::Pressed;
@@ -95,321 +526,2327 @@ impl ControlInputs{
         if self.down.contains(&code){
             return KeyStatus::Down;
         }
+        if self.released.contains(&code){
+            return KeyStatus::Released;
+        }
         KeyStatus::Up
     }
+
+    // Window key down event, returns false for the OS repeating a key that is already down
+    fn key_pressed(&mut self, code: VirtualKeyCode) -> bool{
+        if self.held.insert(code){
+            self.tapped.insert(code);
+            return true;
+        }
+        false
+    }
+
+    fn key_released(&mut self, code: VirtualKeyCode){
+        self.held.remove(&code);
+    }
+
+    // Keys that count as down for the current tick
+    fn active(&self) -> HashSet<VirtualKeyCode>{
+        self.pressed.union(&self.down).cloned().collect()
+    }
+
+    // Called once at the start of every simulation tick with the window's key state.
+    // A key tapped and let go between two ticks is still seen as Pressed for one tick.
+    fn sample(&mut self){
+        let active = self.held.union(&self.tapped).cloned().collect();
+        self.tapped.clear();
+        self.advance(active);
+    }
+
+    // Moves every key one transition along given the keys that are down this tick
+    fn advance(&mut self, active: HashSet<VirtualKeyCode>){
+        let was_active = self.active();
+        self.pressed = active.difference(&was_active).cloned().collect();
+        self.down = active.intersection(&was_active).cloned().collect();
+        self.released = was_active.difference(&active).cloned().collect();
+    }
 }
 
-// SYSTEMS
-pub struct UpdateTime;
-impl<'d, 'w: 'd> System<'d, 'w, World> for UpdateTime{
-    type SystemData = (
-        Write<'d, Time>
-    );
+// What a ship is being told to do this tick, whoever is flying it
+#[derive(Copy, Clone, Default)]
+pub struct Actions{
+    // 0 to 1
+    thrust: f32,
+    // -1 to 1, positive turns counter-clockwise
+    turn: f32,
+    fire: bool,
+}
 
-    fn run(&self, (mut time): Self::SystemData) {
-        let current = std::time::Instant::now();
-        time.delta = current.duration_since(time.last).as_secs_f64();
-        time.total = current.duration_since(time.beginning).as_secs_f64();
-        time.last = current;
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Difficulty{
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty{
+    fn parse(name: &str) -> Option<Self>{
+        match name{
+            "easy" => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard" => Some(Difficulty::Hard),
+            _ => None,
+        }
+    }
+
+    // Seconds between decisions
+    fn reaction_time(&self) -> f64{
+        match self{
+            Difficulty::Easy => 0.5,
+            Difficulty::Normal => 0.25,
+            Difficulty::Hard => 0.1,
+        }
+    }
+
+    // Largest random aim offset in degrees
+    fn aim_error(&self) -> f32{
+        match self{
+            Difficulty::Easy => 20.0,
+            Difficulty::Normal => 8.0,
+            Difficulty::Hard => 2.0,
+        }
+    }
+
+    // How close in degrees to the aim heading before pulling the trigger
+    fn fire_tolerance(&self) -> f32{
+        match self{
+            Difficulty::Easy => 10.0,
+            Difficulty::Normal => 6.0,
+            Difficulty::Hard => 4.0,
+        }
     }
 }
 
-pub struct ApplyVelocities;
-impl<'d, 'w: 'd> System<'d, 'w, World> for ApplyVelocities{
-    type SystemData = (
-        ReadComp<'d, Velocity>,
-        Read<'d, Time>,
-        WriteComp<'d, Position>
-    );
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum ControlSource{
+    // A local player, indexes both the key binding sets and the connected gamepads
+    Human(usize),
+    Ai(Difficulty),
+}
 
-    fn run(&self, (vels, time, mut positions): Self::SystemData) {
-        for (vel, position) in (&vels, &mut positions).join(){
-            position.x += vel.x * time.delta as f32;
-            position.y += vel.y * time.delta as f32;
+// Ships flown from the keyboard, each needs a set of key bindings
+fn human_ships(enemy_source: ControlSource) -> usize{
+    match enemy_source{
+        ControlSource::Human(_) => ENEMY_SHIP + 1,
+        ControlSource::Ai(_) => ENEMY_SHIP,
+    }
+}
 
-            if position.x < -10.5{
-                position.x = 21.0 - position.x.abs();
-            } else if position.x > 10.5{
-                position.x = -21.0 + position.x.abs();
-            }
-            
-            if position.y < -10.5{
-                position.y = 21.0 - position.y.abs();
-            } else if position.y > 10.5{
-                position.y = -21.0 + position.y.abs();
-            }
+// What an AI pilot decided on its last think
+#[derive(Copy, Clone)]
+pub struct AiState{
+    next_think: f64,
+    // Degrees, like Rotation
+    heading: f32,
+    thrust: f32,
+    in_range: bool,
+}
+
+impl Default for AiState{
+    fn default() -> Self{
+        AiState{
+            next_think: 0.0,
+            heading: 0.0,
+            thrust: 0.0,
+            in_range: false,
         }
     }
 }
 
-pub struct ApplyRotationVelocities;
-impl<'d, 'w: 'd> System<'d, 'w, World> for ApplyRotationVelocities{
-    type SystemData = (
-        ReadComp<'d, RotationVelocity>,
-        Read<'d, Time>,
-        WriteComp<'d, Rotation>
-    );
+// Marks a ship as driven by a control source, ReadControllers turns the source into Actions
+#[derive(Copy, Clone)]
+pub struct Controller{
+    source: ControlSource,
+}
 
-    fn run(&self, (vels, time, mut rots): Self::SystemData) {
-        for (vel, rot) in (&vels, &mut rots).join(){
-            rot.0 += vel.0 * time.delta as f32;
-            rot.0 = rot.0.signum() * rot.0.abs() % 360.0;
+// Stick and trigger readings below this are treated as resting
+const GAMEPAD_DEAD_ZONE: f32 = 0.2;
+
+// One gamepad's state in terms of ship actions
+#[derive(Copy, Clone, Default, Serialize, Deserialize)]
+pub struct PadState{
+    thrust: f32,
+    turn: f32,
+    fire: bool,
+}
+
+impl PadState{
+    fn from_gamepad(gamepad: &gilrs::Gamepad) -> Self{
+        let dead_zone = |value: f32| if value.abs() < GAMEPAD_DEAD_ZONE {0.0} else {value};
+        let trigger = gamepad.button_data(gilrs::Button::RightTrigger2).map(|data| data.value()).unwrap_or(0.0);
+        PadState{
+            thrust: dead_zone(trigger),
+            // Stick right turns clockwise
+            turn: -dead_zone(gamepad.value(gilrs::Axis::LeftStickX)),
+            fire: gamepad.is_pressed(gilrs::Button::South) || gamepad.is_pressed(gilrs::Button::RightTrigger),
         }
     }
 }
 
-pub struct ApplyControls;
-impl<'d, 'w: 'd> System<'d, 'w, World> for ApplyControls{
-    type SystemData = (
-        WriteComp<'d, RotationVelocity>,
-        WriteComp<'d, Velocity>,
-        WriteComp<'d, Position>,
-        WriteComp<'d, Radius>,
-        WriteComp<'d, Bullet>,
-        WriteComp<'d, Health>,
-        ReadComp<'d, Rotation>,
-        ReadComp<'d, Player>,
-        ReadComp<'d, Enemy>,
-        Read<'d, ControlInputs>,
-        Read<'d, Time>,
-        Write<'d, EntityStorage>,
-    );
+// Connected gamepads in connection order, sampled once per frame
+pub struct GamepadInputs{
+    pads: Vec<PadState>,
+}
 
-    fn run(&self, (mut a_vels, mut vels, mut positions, mut radii, mut bullets, mut healths, rots, players, enemies, inputs, time, mut ents): Self::SystemData) {
-        let mut new_bullet_position = None;
-        for (vel, a_vel, rot, player, position) in (&mut vels, &mut a_vels, &rots, &players, &positions).join(){
-            let mut turn_val = 0.0;
-            match inputs.key_status(VirtualKeyCode::A){
-                KeyStatus::Up => {},
-                _ => {turn_val += 180.0;},
-            }
-            match inputs.key_status(VirtualKeyCode::D){
-                KeyStatus::Up => {},
-                _ => {turn_val -= 180.0;},
-            }
-            if turn_val == 0.0 && a_vel.0.abs() != 0.0{
-                turn_val = -a_vel.0.signum() * 180.0;
-            }
-            a_vel.0 += turn_val * time.delta as f32;
+impl GamepadInputs{
+    fn sample(&mut self, gilrs: &gilrs::Gilrs){
+        self.pads.clear();
+        for (id, gamepad) in gilrs.gamepads(){
+            self.pads.push(PadState::from_gamepad(&gamepad));
+        }
+    }
+}
+
+// One player's keys, any key in a list triggers the action
+#[derive(Clone, Serialize, Deserialize)]
+pub struct KeyBindings{
+    thrust: Vec<VirtualKeyCode>,
+    turn_left: Vec<VirtualKeyCode>,
+    turn_right: Vec<VirtualKeyCode>,
+    fire: Vec<VirtualKeyCode>,
+}
+
+fn any_held(inputs: &ControlInputs, keys: &[VirtualKeyCode]) -> bool{
+    for key in keys.iter(){
+        if inputs.key_status(*key).is_held(){
+            return true;
+        }
+    }
+    false
+}
+
+impl KeyBindings{
+    fn actions(&self, inputs: &ControlInputs) -> Actions{
+        let mut actions = Actions::default();
+        if any_held(inputs, &self.thrust){
+            actions.thrust = 1.0;
+        }
+        if any_held(inputs, &self.turn_left){
+            actions.turn += 1.0;
+        }
+        if any_held(inputs, &self.turn_right){
+            actions.turn -= 1.0;
+        }
+        actions.fire = any_held(inputs, &self.fire);
+        actions
+    }
+}
 
-            let mut forward_val = 0.0;
-            match inputs.key_status(VirtualKeyCode::W){
-                KeyStatus::Up => {},
-                _ => {forward_val += 1.0},
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Bindings{
+    players: Vec<KeyBindings>,
+}
+
+// Keys the window handles itself, never available as bindings
+const RESERVED_KEYS: [VirtualKeyCode; 5] = [
+    VirtualKeyCode::F5,
+    VirtualKeyCode::F11,
+    VirtualKeyCode::Return,
+    VirtualKeyCode::P,
+    VirtualKeyCode::Escape,
+];
+
+impl Bindings{
+    // Rejects a key bound to more than one action, whether for one player or across players,
+    // an action with no keys, and fewer binding sets than there are players at the keyboard
+    fn validate(&self, humans: usize) -> Result<(), String>{
+        if self.players.len() < humans{
+            return Err(format!("{} players need key bindings but only {} are set", humans, self.players.len()));
+        }
+        let mut bound: Vec<(VirtualKeyCode, String)> = Vec::new();
+        for (player, keys) in self.players.iter().enumerate(){
+            let actions = [
+                ("thrust", &keys.thrust),
+                ("turn_left", &keys.turn_left),
+                ("turn_right", &keys.turn_right),
+                ("fire", &keys.fire),
+            ];
+            for (action, action_keys) in actions.iter(){
+                let name = format!("player {} {}", player + 1, action);
+                if action_keys.is_empty(){
+                    return Err(format!("no keys are bound to {}", name));
+                }
+                for key in action_keys.iter(){
+                    if RESERVED_KEYS.contains(key){
+                        return Err(format!("{:?} is reserved and can't be bound to {}", key, name));
+                    }
+                    if let Some((_, other)) = bound.iter().find(|(bound_key, _)| bound_key == key){
+                        return Err(format!("{:?} is bound to both {} and {}", key, other, name));
+                    }
+                    bound.push((*key, name.clone()));
+                }
             }
-            let direction = (
-                (-rot.0 * std::f32::consts::PI/180.0).sin() ,
-                (rot.0 * std::f32::consts::PI/180.0).cos()
-            );
-            vel.x += forward_val * direction.0 * time.delta as f32;
-            vel.y += forward_val * direction.1 * time.delta as f32;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Bindings{
+    fn default() -> Self{
+        Bindings{
+            players: vec![
+                KeyBindings{
+                    thrust: vec![VirtualKeyCode::W],
+                    turn_left: vec![VirtualKeyCode::A],
+                    turn_right: vec![VirtualKeyCode::D],
+                    fire: vec![VirtualKeyCode::S],
+                },
+                KeyBindings{
+                    thrust: vec![VirtualKeyCode::Up],
+                    turn_left: vec![VirtualKeyCode::Left],
+                    turn_right: vec![VirtualKeyCode::Right],
+                    fire: vec![VirtualKeyCode::Down],
+                },
+            ],
+        }
+    }
+}
+
+// CONFIG
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct Config{
+    controls: Bindings,
+    waves: WaveCurve,
+}
+
+// How each wave of asteroids grows over the one before it, every value stops growing at its max
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct WaveCurve{
+    count: u32,
+    count_step: u32,
+    max_count: u32,
+    speed: f32,
+    speed_step: f32,
+    max_speed: f32,
+    spin: f32,
+    spin_step: f32,
+    max_spin: f32,
+    // Seconds between clearing a wave and the next one arriving
+    delay: f32,
+}
+
+impl Default for WaveCurve{
+    fn default() -> Self{
+        WaveCurve{
+            count: 4,
+            count_step: 1,
+            max_count: 12,
+            speed: 2.0,
+            speed_step: 0.25,
+            max_speed: 5.0,
+            spin: 90.0,
+            spin_step: 15.0,
+            max_spin: 270.0,
+            delay: 3.0,
+        }
+    }
+}
+
+impl WaveCurve{
+    fn validate(&self) -> Result<(), String>{
+        if self.count == 0{
+            return Err("count must be at least 1".to_string());
+        }
+        if self.max_count < self.count || self.max_speed < self.speed || self.max_spin < self.spin{
+            return Err("a max is below its starting value".to_string());
+        }
+        if !(self.speed > 0.0 && self.spin > 0.0){
+            return Err("speed and spin must be above 0".to_string());
+        }
+        if self.speed_step < 0.0 || self.spin_step < 0.0 || self.delay < 0.0{
+            return Err("steps and delay can't be negative".to_string());
+        }
+        Ok(())
+    }
+
+    // Wave numbers start at 1. Saturates rather than overflowing however long a match runs or large the steps are.
+    fn count(&self, wave: u32) -> u32{
+        self.count.saturating_add(self.count_step.saturating_mul(wave.saturating_sub(1))).min(self.max_count)
+    }
+
+    fn speed(&self, wave: u32) -> f32{
+        (self.speed + self.speed_step * wave.saturating_sub(1) as f32).min(self.max_speed)
+    }
+
+    fn spin(&self, wave: u32) -> f32{
+        (self.spin + self.spin_step * wave.saturating_sub(1) as f32).min(self.max_spin)
+    }
+}
+
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+// A missing file means the defaults, anything unreadable or invalid is an error.
+// humans is how many ships are flown from the keyboard and so need bindings.
+fn load_config(path: &str, humans: usize) -> Result<Config, String>{
+    let text = match std::fs::read_to_string(path){
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Config::default());
+        },
+        Err(err) => {
+            return Err(format!("could not read {}: {}", path, err));
+        },
+    };
+    let config: Config = toml::from_str(&text).map_err(|err| format!("could not parse {}: {}", path, err))?;
+    config.controls.validate(humans).map_err(|err| format!("invalid controls in {}: {}", path, err))?;
+    config.waves.validate().map_err(|err| format!("invalid waves in {}: {}", path, err))?;
+    Ok(config)
+}
+
+// INPUT RECORDING
+const RECORDING_VERSION: u32 = 8;
+
+// Everything besides the inputs that decides how a recorded match plays out
+#[derive(Serialize, Deserialize)]
+struct RecordingHeader{
+    version: u32,
+    seed: u64,
+    // Whether the first round started straight away instead of waiting on the title screen
+    skip_title: bool,
+    // Full arena size, as given to --arena
+    arena: (f32, f32),
+    // Whether recorded input or the AI flies the enemy ship
+    enemy_source: ControlSource,
+    // Recorded keys only mean the same actions under the same bindings
+    controls: Bindings,
+    waves: WaveCurve,
+}
+
+impl RecordingHeader{
+    // Rejects headers this build can't play back faithfully
+    fn validate(&self) -> Result<(), String>{
+        if self.version != RECORDING_VERSION{
+            return Err(format!("recording version {} is not supported, expected {}", self.version, RECORDING_VERSION));
+        }
+        if !(self.arena.0 > 0.0 && self.arena.1 > 0.0){
+            return Err(format!("recorded arena {}x{} is not a positive size", self.arena.0, self.arena.1));
+        }
+        self.controls.validate(self.humans()).map_err(|err| format!("recorded controls are invalid: {}", err))?;
+        self.waves.validate().map_err(|err| format!("recorded waves are invalid: {}", err))?;
+        Ok(())
+    }
+
+    // Ships in the recorded match that were flown from the keyboard
+    fn humans(&self) -> usize{
+        human_ships(self.enemy_source)
+    }
+}
+
+// The keys down and gamepad states as the simulation saw them on one tick,
+// the pressed/down/released transitions follow from consecutive ticks
+#[derive(Serialize, Deserialize)]
+struct RecordedTick{
+    keys: Vec<VirtualKeyCode>,
+    pads: Vec<PadState>,
+}
+
+fn sorted_keys(keys: &HashSet<VirtualKeyCode>) -> Vec<VirtualKeyCode>{
+    let mut keys: Vec<VirtualKeyCode> = keys.iter().cloned().collect();
+    keys.sort();
+    keys
+}
+
+// Writes a header followed by one RecordedTick per simulation tick
+pub struct InputRecorder{
+    writer: BufWriter<File>,
+}
+
+impl InputRecorder{
+    fn create(path: &str, header: &RecordingHeader) -> bincode::Result<Self>{
+        let mut writer = BufWriter::new(File::create(path)?);
+        bincode::serialize_into(&mut writer, header)?;
+        Ok(InputRecorder{writer})
+    }
+
+    fn record(&mut self, inputs: &ControlInputs, pads: &GamepadInputs) -> bincode::Result<()>{
+        bincode::serialize_into(&mut self.writer, &RecordedTick{
+            keys: sorted_keys(&inputs.active()),
+            pads: pads.pads.clone(),
+        })
+    }
+}
+
+pub struct InputReplay{
+    reader: BufReader<File>,
+    header: RecordingHeader,
+}
+
+impl InputReplay{
+    fn open(path: &str) -> bincode::Result<Self>{
+        let mut reader = BufReader::new(File::open(path)?);
+        let header: RecordingHeader = bincode::deserialize_from(&mut reader)?;
+        header.validate().map_err(|err| Box::new(bincode::ErrorKind::Custom(err)))?;
+        Ok(InputReplay{reader, header})
+    }
+
+    // Advances the inputs to the next recorded tick in place of sampling, returns false once the recording runs out.
+    // Anything other than running out means the file is damaged and is passed on as an error.
+    fn next_tick(&mut self, inputs: &mut ControlInputs, pads: &mut GamepadInputs) -> bincode::Result<bool>{
+        match bincode::deserialize_from::<_, RecordedTick>(&mut self.reader){
+            Ok(tick) => {
+                inputs.advance(tick.keys.into_iter().collect());
+                pads.pads = tick.pads;
+                Ok(true)
+            },
+            Err(err) => match *err{
+                bincode::ErrorKind::Io(ref io) if io.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+                _ => Err(err),
+            },
+        }
+    }
+}
+
+// MATCH
+// Round wins that take the match
+const ROUNDS_TO_WIN: u32 = 3;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GameState{
+    Title,
+    Playing,
+    Paused,
+    // The last ship standing, None when the last ones went down on the same tick
+    RoundOver{winner: Option<usize>},
+    GameOver{winner: usize},
+}
+
+pub struct Match{
+    state: GameState,
+    // Round wins and points over the whole match, indexed by ship and sized to the ships spawned each round
+    wins: Vec<u32>,
+    scores: Vec<u64>,
+    round: u32,
+    // Simulation tick the current round started on, for the round timer
+    round_start: u64,
+    enemy_source: ControlSource,
+}
+
+fn ship_name(ship: usize) -> String{
+    match ship{
+        PLAYER_SHIP => "Player".to_string(),
+        ENEMY_SHIP => "Enemy".to_string(),
+        _ => format!("Ship {}", ship + 1),
+    }
+}
+
+// Per ship totals in ship order, such as "2 - 1"
+fn tally<T: std::fmt::Display>(values: &[T]) -> String{
+    values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(" - ")
+}
+
+impl Match{
+    fn new(enemy_source: ControlSource) -> Self{
+        Match{
+            state: GameState::Title,
+            wins: Vec::new(),
+            scores: Vec::new(),
+            round: 0,
+            round_start: 0,
+            enemy_source,
+        }
+    }
+
+    // Grows the per ship totals to cover a ship, however many a round spawns
+    fn track_ship(&mut self, ship: usize){
+        if self.wins.len() <= ship{
+            self.wins.resize(ship + 1, 0);
+            self.scores.resize(ship + 1, 0);
+        }
+    }
+
+    // What the window title and console show for the current state
+    fn message(&self) -> String{
+        match self.state{
+            GameState::Title => "Press Enter to start".to_string(),
+            GameState::Playing => format!("Round {}, wins {}", self.round, tally(&self.wins)),
+            GameState::Paused => "Paused, press P or Escape to resume".to_string(),
+            GameState::RoundOver{winner: Some(winner)} => format!(
+                "{} wins round {}, points {}, press Enter for the next round",
+                ship_name(winner), self.round, tally(&self.scores)
+            ),
+            GameState::RoundOver{winner: None} => format!(
+                "Round {} is a draw, points {}, press Enter for the next round",
+                self.round, tally(&self.scores)
+            ),
+            GameState::GameOver{winner} => format!(
+                "{} wins the match, wins {}, points {}, press Enter to return to the title",
+                ship_name(winner), tally(&self.wins), tally(&self.scores)
+            ),
+        }
+    }
+}
+
+// SYSTEMS
+pub struct UpdateTime;
+impl<'d, 'w: 'd> System<'d, 'w, World> for UpdateTime{
+    type SystemData = (
+        Write<'d, Time>
+    );
+
+    fn run(&self, (mut time): Self::SystemData) {
+        time.tick += 1;
+        time.total = time.tick as f64 * time.delta;
+    }
+}
+
+pub struct ApplyVelocities;
+impl<'d, 'w: 'd> System<'d, 'w, World> for ApplyVelocities{
+    type SystemData = (
+        ReadComp<'d, Velocity>,
+        Read<'d, Time>,
+        Read<'d, WorldBounds>,
+        WriteComp<'d, Position>
+    );
+
+    fn run(&self, (vels, time, bounds, mut positions): Self::SystemData) {
+        for (vel, position) in (&vels, &mut positions).join(){
+            position.x += vel.x * time.delta as f32;
+            position.y += vel.y * time.delta as f32;
+            bounds.wrap(position);
+        }
+    }
+}
+
+pub struct ApplyRotationVelocities;
+impl<'d, 'w: 'd> System<'d, 'w, World> for ApplyRotationVelocities{
+    type SystemData = (
+        ReadComp<'d, RotationVelocity>,
+        Read<'d, Time>,
+        WriteComp<'d, Rotation>
+    );
+
+    fn run(&self, (vels, time, mut rots): Self::SystemData) {
+        for (vel, rot) in (&vels, &mut rots).join(){
+            rot.0 += vel.0 * time.delta as f32;
+            rot.0 = rot.0.signum() * rot.0.abs() % 360.0;
+        }
+    }
+}
+
+pub struct ReadControllers;
+impl<'d, 'w: 'd> System<'d, 'w, World> for ReadControllers{
+    type SystemData = (
+        ReadComp<'d, Controller>,
+        WriteComp<'d, Actions>,
+        Read<'d, ControlInputs>,
+        Read<'d, GamepadInputs>,
+        Read<'d, Bindings>,
+    );
+
+    fn run(&self, (controllers, mut actions, inputs, pads, bindings): Self::SystemData) {
+        for (controller, action) in (&controllers, &mut actions).join(){
+            match controller.source{
+                // The keyboard and the player's gamepad both work, whichever is pushed harder wins
+                ControlSource::Human(player) => {
+                    let mut combined = match bindings.players.get(player){
+                        Some(keys) => keys.actions(&inputs),
+                        None => Actions::default(),
+                    };
+                    if let Some(pad) = pads.pads.get(player){
+                        combined.thrust = combined.thrust.max(pad.thrust);
+                        combined.turn = (combined.turn + pad.turn).max(-1.0).min(1.0);
+                        combined.fire = combined.fire || pad.fire;
+                    }
+                    *action = combined;
+                },
+                // AiControl fills these in
+                ControlSource::Ai(_) => {},
+            }
+        }
+    }
+}
+
+// Heading in degrees, matching Rotation, that faces along an offset
+fn heading_of(x: f32, y: f32) -> f32{
+    (-x).atan2(y).to_degrees()
+}
+
+// Signed turn in degrees from one heading to another, the short way round
+fn angle_difference(from: f32, to: f32) -> f32{
+    let mut difference = (to - from) % 360.0;
+    if difference > 180.0{
+        difference -= 360.0;
+    } else if difference < -180.0{
+        difference += 360.0;
+    }
+    difference
+}
+
+// Time until a projectile fired at speed meets a target at offset moving at velocity, both relative to the shooter
+fn intercept_time(offset: (f32, f32), velocity: (f32, f32), speed: f32) -> Option<f32>{
+    let a = velocity.0 * velocity.0 + velocity.1 * velocity.1 - speed * speed;
+    let b = 2.0 * (offset.0 * velocity.0 + offset.1 * velocity.1);
+    let c = offset.0 * offset.0 + offset.1 * offset.1;
+
+    if a.abs() < 1e-6{
+        return if b < 0.0 {Some(-c / b)} else {None};
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0{
+        return None;
+    }
+    let root = discriminant.sqrt();
+    let first = (-b - root) / (2.0 * a);
+    let second = (-b + root) / (2.0 * a);
+    match (first > 0.0, second > 0.0){
+        (true, true) => Some(first.min(second)),
+        (true, false) => Some(first),
+        (false, true) => Some(second),
+        (false, false) => None,
+    }
+}
+
+// Closer than this an approaching asteroid takes priority over the target
+const AI_AVOID_DISTANCE: f32 = 2.5;
+// The AI closes in until it is this far from its target
+const AI_PREFERRED_DISTANCE: f32 = 6.0;
+const AI_MAX_SPEED: f32 = 4.0;
+
+pub struct AiControl;
+impl<'d, 'w: 'd> System<'d, 'w, World> for AiControl{
+    type SystemData = (
+        ReadComp<'d, Controller>,
+        WriteComp<'d, Actions>,
+        WriteComp<'d, AiState>,
+        ReadComp<'d, Ship>,
+        ReadComp<'d, Position>,
+        ReadComp<'d, Velocity>,
+        ReadComp<'d, Rotation>,
+        ReadComp<'d, RotationVelocity>,
+        ReadComp<'d, Weapon>,
+        ReadComp<'d, Asteroid>,
+        Read<'d, WorldBounds>,
+        (Read<'d, Time>, Write<'d, GameRng>),
+    );
+
+    fn run(&self, (controllers, mut actions, mut ai_states, ships, positions, vels, rots, a_vels, weapons, asteroids, bounds, (time, mut rng)): Self::SystemData) {
+        let mut ship_states = Vec::new();
+        for (ship, position, vel, a_vel) in (&ships, &positions, &vels, &a_vels).join(){
+            ship_states.push((ship.0, *position, *vel, a_vel.0));
+        }
+        let mut hazards = Vec::new();
+        for (asteroid, position, vel) in (&asteroids, &positions, &vels).join(){
+            hazards.push((*position, *vel));
+        }
+
+        for (controller, action, state, ship, rot, weapon) in (&controllers, &mut actions, &mut ai_states, &ships, &rots, &weapons).join(){
+            let difficulty = match controller.source{
+                ControlSource::Ai(difficulty) => difficulty,
+                ControlSource::Human(_) => continue,
+            };
+            let (position, vel, spin) = match ship_states.iter().find(|(id, _, _, _)| *id == ship.0){
+                Some((_, position, vel, spin)) => (*position, *vel, *spin),
+                None => continue,
+            };
+
+            // Decisions only happen every reaction period, in between the ship acts on what it last saw
+            if time.total >= state.next_think{
+                state.next_think = time.total + difficulty.reaction_time();
+                state.heading = rot.0;
+                state.thrust = 0.0;
+                state.in_range = false;
+
+                let mut threat: Option<(f32, f32)> = None;
+                let mut threat_distance = AI_AVOID_DISTANCE;
+                for (hazard_position, hazard_vel) in hazards.iter(){
+                    let (x, y) = bounds.offset(&position, hazard_position);
+                    let distance = (x * x + y * y).sqrt();
+                    let closing = x * (hazard_vel.x - vel.x) + y * (hazard_vel.y - vel.y) < 0.0;
+                    if closing && distance < threat_distance{
+                        threat = Some((x, y));
+                        threat_distance = distance;
+                    }
+                }
+
+                let mut target: Option<((f32, f32), Velocity)> = None;
+                let mut target_distance = std::f32::MAX;
+                for (id, target_position, target_vel, _) in ship_states.iter(){
+                    if *id == ship.0{
+                        continue;
+                    }
+                    let (x, y) = bounds.offset(&position, target_position);
+                    let distance = (x * x + y * y).sqrt();
+                    if distance < target_distance{
+                        target = Some(((x, y), *target_vel));
+                        target_distance = distance;
+                    }
+                }
+
+                if let Some((x, y)) = threat{
+                    state.heading = heading_of(-x, -y);
+                    state.thrust = 1.0;
+                } else if let Some((offset, target_vel)) = target{
+                    // Bullets inherit the shooter's velocity, so lead using the relative velocity
+                    let relative = (target_vel.x - vel.x, target_vel.y - vel.y);
+                    let lead = intercept_time(offset, relative, weapon.speed).unwrap_or(target_distance / weapon.speed);
+                    let aim = (offset.0 + relative.0 * lead, offset.1 + relative.1 * lead);
+                    let error = difficulty.aim_error();
+                    state.heading = heading_of(aim.0, aim.1) + rng.gen_range(-error, error);
+
+                    let speed = (vel.x * vel.x + vel.y * vel.y).sqrt();
+                    if target_distance > AI_PREFERRED_DISTANCE && speed < AI_MAX_SPEED{
+                        state.thrust = 1.0;
+                    }
+                    state.in_range = target_distance < weapon.range;
+                }
+            }
+
+            // Steering runs every tick, easing the spin down as the heading lines up
+            let remaining = angle_difference(rot.0, state.heading);
+            let wanted_spin = (remaining * 4.0).max(-180.0).min(180.0);
+            action.turn = ((wanted_spin - spin) / 90.0).max(-1.0).min(1.0);
+            action.thrust = if remaining.abs() < 45.0 {state.thrust} else {0.0};
+            action.fire = state.in_range && remaining.abs() < difficulty.fire_tolerance();
+        }
+    }
+}
+
+pub struct ApplyControls;
+impl<'d, 'w: 'd> System<'d, 'w, World> for ApplyControls{
+    type SystemData = (
+        WriteComp<'d, RotationVelocity>,
+        WriteComp<'d, Velocity>,
+        ReadComp<'d, Position>,
+        ReadComp<'d, Rotation>,
+        WriteComp<'d, Weapon>,
+        ReadComp<'d, Actions>,
+        ReadComp<'d, Ship>,
+        Read<'d, Time>,
+        Write<'d, ShotQueue>,
+    );
+
+    fn run(&self, (mut a_vels, mut vels, positions, rots, mut weapons, actions, ships, time, mut shots): Self::SystemData) {
+        for (vel, a_vel, rot, weapon, action, ship, position) in (&mut vels, &mut a_vels, &rots, &mut weapons, &actions, &ships, &positions).join(){
+            let mut turn_val = action.turn * 180.0;
+            if turn_val == 0.0 && a_vel.0.abs() != 0.0{
+                turn_val = -a_vel.0.signum() * 180.0;
+            }
+            a_vel.0 += turn_val * time.delta as f32;
+
+            let forward_val = action.thrust;
+            let direction = (
+                (-rot.0 * std::f32::consts::PI/180.0).sin() ,
+                (rot.0 * std::f32::consts::PI/180.0).cos()
+            );
+            vel.x += forward_val * direction.0 * time.delta as f32;
+            vel.y += forward_val * direction.1 * time.delta as f32;
+
+            if weapon.update(time.delta as f32, action.fire){
+                shots.shots.push(weapon.shot(ship.0, position, vel, direction));
+            }
+        }
+    }
+}
+
+// A projectile fired this tick, turned into an entity by SpawnShots
+pub struct Shot{
+    position: Position,
+    velocity: Velocity,
+    owner: usize,
+    lifetime: Lifetime,
+}
+
+pub struct ShotQueue{
+    shots: Vec<Shot>,
+}
+
+pub struct SpawnShots;
+impl<'d, 'w: 'd> System<'d, 'w, World> for SpawnShots{
+    type SystemData = (
+        WriteComp<'d, Position>,
+        WriteComp<'d, Velocity>,
+        WriteComp<'d, Radius>,
+        WriteComp<'d, Bullet>,
+        WriteComp<'d, Health>,
+        WriteComp<'d, Collider>,
+        WriteComp<'d, Lifetime>,
+        WriteComp<'d, Owner>,
+        Write<'d, ShotQueue>,
+        Write<'d, EntityStorage>,
+    );
+
+    fn run(&self, (mut positions, mut vels, mut radii, mut bullets, mut healths, mut colliders, mut lifetimes, mut owners, mut shots, mut ents): Self::SystemData) {
+        for shot in shots.shots.drain(..){
+            ents.create_entity()
+                .add(&mut positions, shot.position)
+                .add(&mut vels, shot.velocity)
+                .add(&mut bullets, Bullet{})
+                .add(&mut radii, Radius(0.25))
+                .add(&mut healths, Health(1))
+                .add(&mut colliders, Collider::bullet())
+                .add(&mut lifetimes, shot.lifetime)
+                .add(&mut owners, Owner(shot.owner));
+        }
+    }
+}
+
+pub struct ExpireLifetimes;
+impl<'d, 'w: 'd> System<'d, 'w, World> for ExpireLifetimes{
+    type SystemData = (
+        WriteComp<'d, Lifetime>,
+        ReadComp<'d, Velocity>,
+        WriteComp<'d, Dead>,
+        Read<'d, Time>,
+        Read<'d, EntityStorage>,
+    );
+
+    fn run(&self, (mut lifetimes, vels, mut deads, time, ents): Self::SystemData) {
+        let mut expired = Vec::new();
+        for (lifetime, vel, entity) in (&mut lifetimes, &vels, ents.deref()).join(){
+            lifetime.seconds -= time.delta as f32;
+            lifetime.distance -= (vel.x * vel.x + vel.y * vel.y).sqrt() * time.delta as f32;
+            if lifetime.seconds <= 0.0 || lifetime.distance <= 0.0{
+                expired.push(entity.clone());
+            }
+        }
+        for entity in expired.drain(..){
+            entity.add(&mut deads, Dead{});
+        }
+    }
+}
+
+fn collision_check(rad_one: &Radius, pos_one: &Position, rad_two: &Radius, pos_two: &Position, bounds: &WorldBounds) -> bool{
+    let (x, y) = bounds.offset(pos_one, pos_two);
+    x.powi(2) + y.powi(2) <= (rad_one.0 + rad_two.0).powi(2)
+}
+
+// An entity's entry in this tick's CollisionGrid, lets systems look up per-entity results of the collision events
+#[derive(Copy, Clone)]
+pub struct GridIndex(usize);
+
+struct GridEntry{
+    // Insertion order, lets each pair of entries be checked exactly once
+    index: usize,
+    entity: Entity,
+    position: Position,
+    velocity: Velocity,
+    radius: Radius,
+    collider: Collider,
+}
+
+// Uniform grid over the wrapped arena, rebuilt every tick so collision checks only look at nearby cells
+pub struct CollisionGrid{
+    columns: usize,
+    rows: usize,
+    cell_width: f32,
+    cell_height: f32,
+    cells: Vec<Vec<GridEntry>>,
+    // Number of entries inserted since the last reset
+    entries: usize,
+}
+
+impl CollisionGrid{
+    fn new() -> Self{
+        CollisionGrid{
+            columns: 1,
+            rows: 1,
+            cell_width: 1.0,
+            cell_height: 1.0,
+            cells: vec![Vec::new()],
+            entries: 0,
+        }
+    }
+
+    // Empties the grid and resizes it so no cell is smaller than min_cell_size,
+    // an entity can then only touch entities in its own or the eight surrounding cells
+    fn reset(&mut self, bounds: &WorldBounds, min_cell_size: f32){
+        self.columns = ((bounds.width() / min_cell_size) as usize).max(1);
+        self.rows = ((bounds.height() / min_cell_size) as usize).max(1);
+        self.cell_width = bounds.width() / self.columns as f32;
+        self.cell_height = bounds.height() / self.rows as f32;
+
+        for cell in self.cells.iter_mut(){
+            cell.clear();
+        }
+        self.cells.resize_with(self.columns * self.rows, Vec::new);
+        self.entries = 0;
+    }
+
+    fn cell_of(&self, position: &Position, bounds: &WorldBounds) -> (usize, usize){
+        let column = ((position.x + bounds.x + bounds.margin) / self.cell_width).max(0.0) as usize;
+        let row = ((position.y + bounds.y + bounds.margin) / self.cell_height).max(0.0) as usize;
+        (column.min(self.columns - 1), row.min(self.rows - 1))
+    }
+
+    fn insert(&mut self, entry: GridEntry, bounds: &WorldBounds){
+        let (column, row) = self.cell_of(&entry.position, bounds);
+        self.cells[row * self.columns + column].push(entry);
+        self.entries += 1;
+    }
+
+    // Indices of the cell holding position and its neighbours, wrapping across the arena edges
+    fn neighbour_cells(&self, position: &Position, bounds: &WorldBounds) -> Vec<usize>{
+        let (column, row) = self.cell_of(position, bounds);
+        let mut cells = Vec::with_capacity(9);
+        for row_offset in 0..3{
+            for column_offset in 0..3{
+                let neighbour_row = (row + self.rows + row_offset - 1) % self.rows;
+                let neighbour_column = (column + self.columns + column_offset - 1) % self.columns;
+                let index = neighbour_row * self.columns + neighbour_column;
+                // Narrow grids wrap onto the same cell more than once
+                if !cells.contains(&index){
+                    cells.push(index);
+                }
+            }
+        }
+        cells
+    }
+}
+
+pub struct BuildCollisionGrid;
+impl<'d, 'w: 'd> System<'d, 'w, World> for BuildCollisionGrid{
+    type SystemData = (
+        ReadComp<'d, Radius>,
+        ReadComp<'d, Position>,
+        ReadComp<'d, Velocity>,
+        ReadComp<'d, Collider>,
+        WriteComp<'d, GridIndex>,
+        Read<'d, EntityStorage>,
+        Read<'d, WorldBounds>,
+        Write<'d, CollisionGrid>,
+    );
+
+    fn run(&self, (radii, positions, vels, colliders, mut grid_indices, ents, bounds, mut grid): Self::SystemData) {
+        // Entities that dropped out of the grid mustn't keep pointing at someone else's entry
+        let mut stale = Vec::new();
+        for (grid_index, entity) in (&grid_indices, ents.deref()).join(){
+            stale.push(entity.clone());
+        }
+        for entity in stale.drain(..){
+            entity.remove(&mut grid_indices);
+        }
+
+        let mut largest_radius: f32 = 0.0;
+        for (radius, entity) in (&radii, ents.deref()).join(){
+            largest_radius = largest_radius.max(radius.0);
+        }
+
+        grid.reset(&bounds, (largest_radius * 2.0).max(0.1));
+        let mut index = 0;
+        for (position, vel, radius, collider, entity) in (&positions, &vels, &radii, &colliders, ents.deref()).join(){
+            grid.insert(GridEntry{
+                index,
+                entity: entity.clone(),
+                position: *position,
+                velocity: *vel,
+                radius: *radius,
+                collider: *collider,
+            }, &bounds);
+            entity.add(&mut grid_indices, GridIndex(index));
+            index += 1;
+        }
+    }
+}
+
+pub struct CollisionEvent{
+    first: Entity,
+    first_index: usize,
+    first_collider: Collider,
+    second: Entity,
+    second_index: usize,
+    second_collider: Collider,
+    // Point on the edge of the first entity facing the second
+    contact: Position,
+    // Velocity of the second entity as seen from the first
+    relative_velocity: Velocity,
+}
+
+// Every overlapping pair found this tick, cleared when the next collision check starts
+pub struct CollisionEvents{
+    events: Vec<CollisionEvent>,
+}
+
+use std::ops::Deref;
+
+// Health taken off one entity by another this tick, cleared when the next damage check starts
+pub struct DamageEvent{
+    // Ship that fired the projectile doing the damage, None for anything that wasn't fired
+    shooter: Option<usize>,
+    victim_collider: Collider,
+    victim_radius: f32,
+    amount: isize,
+    // This hit is the one that took the victim to zero
+    lethal: bool,
+}
+
+pub struct DamageEvents{
+    events: Vec<DamageEvent>,
+}
+pub struct CollisionCheck;
+impl<'d, 'w: 'd> System<'d, 'w, World> for CollisionCheck{
+    type SystemData = (
+        Read<'d, CollisionGrid>,
+        Read<'d, WorldBounds>,
+        Write<'d, CollisionEvents>,
+    );
+
+    fn run(&self, (grid, bounds, mut events): Self::SystemData) {
+        events.events.clear();
+        for cell in grid.cells.iter(){
+            for first in cell.iter(){
+                for neighbour in grid.neighbour_cells(&first.position, &bounds){
+                    for second in grid.cells[neighbour].iter(){
+                        if second.index <= first.index{
+                            continue;
+                        }
+                        // Neither side cares about the other's layer
+                        if first.collider.mask & second.collider.layer.bit() == 0 && second.collider.mask & first.collider.layer.bit() == 0{
+                            continue;
+                        }
+                        if !collision_check(&first.radius, &first.position, &second.radius, &second.position, &bounds){
+                            continue;
+                        }
+
+                        let (x, y) = bounds.offset(&first.position, &second.position);
+                        let distance = (x * x + y * y).sqrt().max(std::f32::EPSILON);
+                        let mut contact = Position{
+                            x: first.position.x + x / distance * first.radius.0,
+                            y: first.position.y + y / distance * first.radius.0,
+                        };
+                        bounds.wrap(&mut contact);
+
+                        events.events.push(CollisionEvent{
+                            first: first.entity.clone(),
+                            first_index: first.index,
+                            first_collider: first.collider,
+                            second: second.entity.clone(),
+                            second_index: second.index,
+                            second_collider: second.collider,
+                            contact,
+                            relative_velocity: Velocity{
+                                x: second.velocity.x - first.velocity.x,
+                                y: second.velocity.y - first.velocity.y,
+                            },
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub struct DamagerCollisionCheck;
+impl<'d, 'w: 'd> System<'d, 'w, World> for DamagerCollisionCheck{
+    type SystemData = (
+        ReadComp<'d, GridIndex>,
+        ReadComp<'d, Collider>,
+        WriteComp<'d, Health>,
+        Read<'d, CollisionGrid>,
+        Read<'d, CollisionEvents>,
+        Read<'d, DamageTable>,
+        ReadComp<'d, Invulnerable>,
+        ReadComp<'d, Radius>,
+        Write<'d, DamageEvents>,
+        ReadComp<'d, Owner>,
+    );
+
+    fn run(&self, (grid_indices, colliders, mut healths, grid, events, damage_table, invulnerables, radii, mut damage_events, owners): Self::SystemData) {
+        damage_events.events.clear();
+        // Still hits whatever it runs into, it just doesn't get hurt back
+        let mut shielded = vec![false; grid.entries];
+        for (_, grid_index) in (&invulnerables, &grid_indices).join(){
+            shielded[grid_index.0] = true;
+        }
+        // Ship that fired each grid entry, None for anything that wasn't fired
+        let mut shooters = vec![None; grid.entries];
+        for (owner, grid_index) in (&owners, &grid_indices).join(){
+            shooters[grid_index.0] = Some(owner.0);
+        }
+
+        // Hits owed to each grid entry as (shooter, amount), both sides of an event are settled in the one pass
+        let mut hits = vec![Vec::new(); grid.entries];
+        for event in events.events.iter(){
+            let sides = [
+                (event.first_index, &event.first_collider, event.second_index, &event.second_collider),
+                (event.second_index, &event.second_collider, event.first_index, &event.first_collider),
+            ];
+            for &(victim_index, victim, attacker_index, attacker) in sides.iter(){
+                if victim.mask & attacker.layer.bit() == 0{
+                    continue;
+                }
+                let shooter = shooters[attacker_index];
+                let amount = damage_table.damage(
+                    attacker, shooter.or(attacker.owner),
+                    victim, shooters[victim_index].or(victim.owner)
+                );
+                if amount != 0{
+                    hits[victim_index].push((shooter, amount));
+                }
+            }
+        }
+
+        for (grid_index, collider, health, radius) in (&grid_indices, &colliders, &mut healths, &radii).join(){
+            if shielded[grid_index.0]{
+                continue;
+            }
+            for &(shooter, amount) in hits[grid_index.0].iter(){
+                let was_alive = health.0 > 0;
+                health.0 -= amount;
+                damage_events.events.push(DamageEvent{
+                    shooter,
+                    victim_collider: *collider,
+                    victim_radius: radius.0,
+                    amount,
+                    lethal: was_alive && health.0 <= 0,
+                });
+            }
+        }
+    }
+}
+
+const SHIP_HEALTH: isize = 5;
+const STARTING_LIVES: u32 = 3;
+// Seconds a ship stays out of play after losing a life
+const RESPAWN_DELAY: f32 = 2.0;
+const INVULNERABLE_TIME: f32 = 3.0;
+// No asteroid or bullet may be this close to where a ship respawns
+const SAFE_SPAWN_RADIUS: f32 = 3.0;
+// Random spots tried per tick, a crowded arena just delays the respawn to a later tick
+const SPAWN_ATTEMPTS: u32 = 16;
+
+// Runs before DestroyZeroHealth, a ship with a life to spare is pulled out of play instead of being destroyed
+pub struct LoseLife;
+impl<'d, 'w: 'd> System<'d, 'w, World> for LoseLife{
+    type SystemData = (
+        ReadComp<'d, Ship>,
+        WriteComp<'d, Health>,
+        WriteComp<'d, Lives>,
+        WriteComp<'d, Position>,
+        WriteComp<'d, Velocity>,
+        WriteComp<'d, RotationVelocity>,
+        WriteComp<'d, Respawn>,
+        Read<'d, EntityStorage>,
+    );
+
+    fn run(&self, (ships, mut healths, mut lives, mut positions, mut vels, mut angle_vels, mut respawns, ents): Self::SystemData) {
+        let mut respawning = Vec::new();
+        for (ship, health, ship_lives, entity) in (&ships, &mut healths, &mut lives, ents.deref()).join(){
+            if health.0 > 0{
+                continue;
+            }
+            ship_lives.0 = ship_lives.0.saturating_sub(1);
+            if ship_lives.0 > 0{
+                health.0 = SHIP_HEALTH;
+                respawning.push(entity.clone());
+            }
+        }
+        for entity in respawning.drain(..){
+            entity.remove(&mut positions);
+            entity.add(&mut vels, Velocity{x: 0.0, y: 0.0});
+            entity.add(&mut angle_vels, RotationVelocity(0.0));
+            entity.add(&mut respawns, Respawn{seconds: RESPAWN_DELAY});
+        }
+    }
+}
+
+// Puts ships back into play once their delay is up, at a random spot clear of asteroids and bullets
+pub struct RespawnShips;
+impl<'d, 'w: 'd> System<'d, 'w, World> for RespawnShips{
+    type SystemData = (
+        ReadComp<'d, Asteroid>,
+        ReadComp<'d, Bullet>,
+        WriteComp<'d, Position>,
+        WriteComp<'d, Respawn>,
+        WriteComp<'d, Invulnerable>,
+        Read<'d, WorldBounds>,
+        Read<'d, Time>,
+        Write<'d, GameRng>,
+        Read<'d, EntityStorage>,
+    );
+
+    fn run(&self, (asteroids, bullets, mut positions, mut respawns, mut invulnerables, bounds, time, mut rng, ents): Self::SystemData) {
+        let mut hazards = Vec::new();
+        for (asteroid, position) in (&asteroids, &positions).join(){
+            hazards.push(*position);
+        }
+        for (bullet, position) in (&bullets, &positions).join(){
+            hazards.push(*position);
+        }
+
+        let mut ready = Vec::new();
+        for (respawn, entity) in (&mut respawns, ents.deref()).join(){
+            respawn.seconds -= time.delta as f32;
+            if respawn.seconds > 0.0{
+                continue;
+            }
+            for attempt in 0..SPAWN_ATTEMPTS{
+                let candidate = Position{x: rng.gen_range(-bounds.x, bounds.x), y: rng.gen_range(-bounds.y, bounds.y)};
+                let safe = hazards.iter().all(|hazard| {
+                    let (dx, dy) = bounds.offset(&candidate, hazard);
+                    dx * dx + dy * dy >= SAFE_SPAWN_RADIUS * SAFE_SPAWN_RADIUS
+                });
+                if safe{
+                    ready.push((entity.clone(), candidate));
+                    break;
+                }
+            }
+        }
+        for (entity, position) in ready.drain(..){
+            entity.remove(&mut respawns);
+            entity.add(&mut positions, position);
+            entity.add(&mut invulnerables, Invulnerable{seconds: INVULNERABLE_TIME});
+        }
+    }
+}
+
+pub struct ExpireInvulnerability;
+impl<'d, 'w: 'd> System<'d, 'w, World> for ExpireInvulnerability{
+    type SystemData = (
+        WriteComp<'d, Invulnerable>,
+        Read<'d, Time>,
+        Read<'d, EntityStorage>,
+    );
+
+    fn run(&self, (mut invulnerables, time, ents): Self::SystemData) {
+        let mut expired = Vec::new();
+        for (invulnerable, entity) in (&mut invulnerables, ents.deref()).join(){
+            invulnerable.seconds -= time.delta as f32;
+            if invulnerable.seconds <= 0.0{
+                expired.push(entity.clone());
+            }
+        }
+        for entity in expired.drain(..){
+            entity.remove(&mut invulnerables);
+        }
+    }
+}
+
+// Points for destroying an asteroid, indexed by asteroid_health - 1 so the smallest are worth the most
+const ASTEROID_POINTS: [u64; 3] = [100, 50, 20];
+// Per point of damage dealt to the opposing ship, plus a bonus for taking one of its lives
+const SHIP_HIT_POINTS: u64 = 50;
+const SHIP_KILL_POINTS: u64 = 250;
+// Seconds after a kill during which the next one raises the combo
+const COMBO_WINDOW: f32 = 2.0;
+const MAX_COMBO: u32 = 8;
+
+fn asteroid_points(radius: f32) -> u64{
+    let index = (asteroid_health(radius) - 1).max(0) as usize;
+    ASTEROID_POINTS[index.min(ASTEROID_POINTS.len() - 1)]
+}
+
+// Credits the ship that fired a projectile with the damage it dealt this tick
+pub struct AwardScore;
+impl<'d, 'w: 'd> System<'d, 'w, World> for AwardScore{
+    type SystemData = (
+        ReadComp<'d, Ship>,
+        WriteComp<'d, Score>,
+        Read<'d, DamageEvents>,
+        Read<'d, Time>,
+    );
+
+    fn run(&self, (ships, mut scores, damage_events, time): Self::SystemData) {
+        // Ship credited, points, and whether it counts as a kill for the combo
+        let mut awards = Vec::new();
+        for event in damage_events.events.iter(){
+            let shooter = match event.shooter{
+                Some(shooter) => shooter,
+                None => continue,
+            };
+            let victim = &event.victim_collider;
+            match victim.layer{
+                Layer::Asteroid if event.lethal => {
+                    awards.push((shooter, asteroid_points(event.victim_radius), true));
+                },
+                Layer::Ship if victim.owner != Some(shooter) => {
+                    awards.push((shooter, SHIP_HIT_POINTS * event.amount as u64, false));
+                    if event.lethal{
+                        awards.push((shooter, SHIP_KILL_POINTS, true));
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        for (ship, score) in (&ships, &mut scores).join(){
+            score.combo_timer -= time.delta as f32;
+            if score.combo_timer <= 0.0{
+                score.combo = 1;
+            }
+            for (shooter, points, kill) in awards.iter(){
+                if *shooter != ship.0{
+                    continue;
+                }
+                if *kill{
+                    score.points += points * score.combo as u64;
+                    score.combo = (score.combo + 1).min(MAX_COMBO);
+                    score.combo_timer = COMBO_WINDOW;
+                } else {
+                    score.points += points;
+                }
+            }
+        }
+    }
+}
+
+pub struct DestroyZeroHealth;
+impl<'d, 'w: 'd> System<'d, 'w, World> for DestroyZeroHealth{
+    type SystemData = (
+        ReadComp<'d, Health>,
+        WriteComp<'d, Dead>,
+        Read<'d, EntityStorage>,
+    );
+
+    fn run(&self, (healths, mut deads, ents): Self::SystemData) {
+        let mut to_kill = Vec::new();
+        for (health, entity) in (&healths, ents.deref()).join(){
+            if health.0 <= 0{
+                to_kill.push(entity.clone());
+            }
+        }
+        for entity in to_kill.drain(..){
+            entity.add(&mut deads, Dead{});
+        }
+    }
+}
+
+const ASTEROID_RADIUS: f32 = 0.5;
+// Asteroids this small or smaller crumble instead of splitting
+const ASTEROID_MIN_RADIUS: f32 = 0.125;
+// Largest random speed added on top of the parent's velocity when splitting
+const ASTEROID_SPLIT_SPEED: f32 = 1.5;
+
+// Every halving in size takes one less hit to destroy, the smallest fragments die in one
+fn asteroid_health(radius: f32) -> isize{
+    (radius / ASTEROID_MIN_RADIUS).log2().round() as isize + 1
+}
+
+// Asteroids shot or rammed down to zero health break into two or three half sized fragments,
+// those despawned for any other reason just vanish
+pub struct SplitAsteroids;
+impl<'d, 'w: 'd> System<'d, 'w, World> for SplitAsteroids{
+    type SystemData = (
+        WriteComp<'d, Asteroid>,
+        WriteComp<'d, Health>,
+        WriteComp<'d, Position>,
+        WriteComp<'d, Velocity>,
+        WriteComp<'d, Radius>,
+        WriteComp<'d, Rotation>,
+        WriteComp<'d, RotationVelocity>,
+        WriteComp<'d, Collider>,
+        Write<'d, GameRng>,
+        Write<'d, EntityStorage>,
+    );
+
+    fn run(&self, (mut asteroids, mut healths, mut positions, mut vels, mut radii, mut angles, mut angle_vels, mut colliders, mut rng, mut ents): Self::SystemData) {
+        let mut broken = Vec::new();
+        for (asteroid, health, position, vel, radius) in (&asteroids, &healths, &positions, &vels, &radii).join(){
+            if health.0 <= 0 && radius.0 >= ASTEROID_MIN_RADIUS * 2.0{
+                broken.push((*position, *vel, radius.0));
+            }
+        }
+        for (position, vel, radius) in broken.drain(..){
+            let child_radius = radius * 0.5;
+            let children = rng.gen_range(2, 4);
+            // Spread the fragments evenly around the parent from a random starting angle
+            let start = rng.gen_range(0.0, std::f32::consts::PI * 2.0);
+            for i in 0..children{
+                let angle = start + std::f32::consts::PI * 2.0 * i as f32 / children as f32;
+                let kick = rng.gen_range(0.0, ASTEROID_SPLIT_SPEED);
+                let spin = rng.gen_range(-90.0, 90.0) * ASTEROID_RADIUS / child_radius;
+                ents.create_entity()
+                    .add(&mut healths, Health(asteroid_health(child_radius)))
+                    .add(&mut positions, Position{x: position.x + angle.cos() * child_radius, y: position.y + angle.sin() * child_radius})
+                    .add(&mut vels, Velocity{x: vel.x + angle.cos() * kick, y: vel.y + angle.sin() * kick})
+                    .add(&mut radii, Radius(child_radius))
+                    .add(&mut angles, Rotation(rng.gen_range(0.0, 360.0)))
+                    .add(&mut angle_vels, RotationVelocity(spin))
+                    .add(&mut asteroids, Asteroid{})
+                    .add(&mut colliders, Collider::asteroid());
+            }
+        }
+    }
+}
+
+pub struct Waves{
+    curve: WaveCurve,
+    // The wave last launched, 0 before the first
+    wave: u32,
+    // Counts down once the arena is clear, the next wave launches when it runs out
+    next_wave_in: f32,
+}
+
+impl Waves{
+    fn new(curve: WaveCurve) -> Self{
+        Waves{
+            curve,
+            wave: 0,
+            next_wave_in: 0.0,
+        }
+    }
+}
+
+// Launches the next wave once every asteroid of the last one is destroyed and the delay has passed.
+// Asteroids start out of sight on the seam where the left and right edges wrap into each other,
+// away from where the ships start at the top and bottom, and drift in from one side or the other
+pub struct SpawnWaves;
+impl<'d, 'w: 'd> System<'d, 'w, World> for SpawnWaves{
+    type SystemData = (
+        WriteComp<'d, Asteroid>,
+        WriteComp<'d, Health>,
+        WriteComp<'d, Position>,
+        WriteComp<'d, Velocity>,
+        WriteComp<'d, Radius>,
+        WriteComp<'d, Rotation>,
+        WriteComp<'d, RotationVelocity>,
+        WriteComp<'d, Collider>,
+        Write<'d, Waves>,
+        Write<'d, EntityStorage>,
+        (Read<'d, WorldBounds>, Read<'d, Time>),
+        Write<'d, GameRng>,
+    );
+
+    fn run(&self, (mut asteroids, mut healths, mut positions, mut vels, mut radii, mut angles, mut angle_vels, mut colliders, mut waves, mut ents, (bounds, time), mut rng): Self::SystemData) {
+        // Asteroids destroyed this tick are still around until the end of it
+        let remaining = (&asteroids, &healths).join().filter(|(asteroid, health)| health.0 > 0).count();
+        if remaining > 0{
+            waves.next_wave_in = waves.curve.delay;
+            return;
+        }
+        waves.next_wave_in -= time.delta as f32;
+        if waves.next_wave_in > 0.0{
+            return;
+        }
+
+        waves.wave += 1;
+        let wave = waves.wave;
+        let (count, speed, spin) = (waves.curve.count(wave), waves.curve.speed(wave), waves.curve.spin(wave));
+        for i in 0..count{
+            // A full size asteroid's radius matches the margin, so on the seam it is entirely off screen
+            let position = Position{x: bounds.x + bounds.margin, y: rng.gen_range(-bounds.y * 0.7, bounds.y * 0.7)};
+            // Head for somewhere in the middle of the arena so every asteroid crosses into view
+            let target = Position{x: rng.gen_range(-bounds.x * 0.5, bounds.x * 0.5), y: rng.gen_range(-bounds.y * 0.5, bounds.y * 0.5)};
+            let (dx, dy) = bounds.offset(&position, &target);
+            let distance = (dx * dx + dy * dy).sqrt().max(std::f32::EPSILON);
+            let launch_speed = rng.gen_range(speed * 0.5, speed);
+            ents.create_entity()
+                .add(&mut healths, Health(asteroid_health(ASTEROID_RADIUS)))
+                .add(&mut positions, position)
+                .add(&mut vels, Velocity{x: dx / distance * launch_speed, y: dy / distance * launch_speed})
+                .add(&mut radii, Radius(ASTEROID_RADIUS))
+                .add(&mut angles, Rotation(rng.gen_range(0.0, 360.0)))
+                .add(&mut angle_vels, RotationVelocity(rng.gen_range(-spin, spin)))
+                .add(&mut asteroids, Asteroid{})
+                .add(&mut colliders, Collider::asteroid());
+        }
+        waves.next_wave_in = waves.curve.delay;
+    }
+}
+
+// Ends the round as soon as a ship is destroyed, a ship going down on the same tick as its killer makes it a draw
+pub struct CheckRoundOver;
+impl<'d, 'w: 'd> System<'d, 'w, World> for CheckRoundOver{
+    type SystemData = (
+        ReadComp<'d, Ship>,
+        ReadComp<'d, Health>,
+        ReadComp<'d, Score>,
+        Write<'d, Match>,
+    );
+
+    fn run(&self, (ships, healths, scores, mut game): Self::SystemData) {
+        let mut alive = Vec::new();
+        let mut ship_count = 0;
+        for (ship, health) in (&ships, &healths).join(){
+            game.track_ship(ship.0);
+            ship_count += 1;
+            if health.0 > 0{
+                alive.push(ship.0);
+            }
+        }
+        // A lone ship plays on until it is destroyed, otherwise the round lasts while two or more survive
+        let over = alive.is_empty() || (alive.len() == 1 && ship_count > 1);
+        if !over{
+            return;
+        }
+        let winner = alive.first().cloned();
+        // Ships are despawned at the end of the tick, bank their points while they're still here
+        for (ship, score) in (&ships, &scores).join(){
+            game.scores[ship.0] += score.points;
+        }
+        game.state = GameState::RoundOver{winner};
+        if let Some(winner) = winner{
+            game.wins[winner] += 1;
+            if game.wins[winner] >= ROUNDS_TO_WIN{
+                game.state = GameState::GameOver{winner};
+            }
+        }
+        println!("{}", game.message());
+    }
+}
+
+//RENDER STUFF
+// Largest centered rectangle of the window with the arena's aspect ratio, the rest is left as black bars
+fn arena_viewport((width, height): (u32, u32), bounds: &WorldBounds) -> glium::Rect{
+    let scale = (width as f32 / bounds.x).min(height as f32 / bounds.y);
+    let viewport_width = ((bounds.x * scale) as u32).min(width);
+    let viewport_height = ((bounds.y * scale) as u32).min(height);
+    glium::Rect{
+        left: (width - viewport_width) / 2,
+        bottom: (height - viewport_height) / 2,
+        width: viewport_width,
+        height: viewport_height,
+    }
+}
+
+// Extrapolates a position by however far ahead of the last simulation step the frame is, in seconds
+fn render_position(position: &Position, vel: &Velocity, ahead: f32) -> [f32; 2]{
+    [position.x + vel.x * ahead, position.y + vel.y * ahead]
+}
+
+#[derive(Copy, Clone)]
+struct Vertex {
+    position: [f32; 2],
+    uv: [f32; 2],
+}
+implement_vertex!(Vertex, position, uv);
+
+// Layers of the sprite texture array, in the order the images are loaded
+#[derive(Copy, Clone)]
+enum Sprite{
+    Player = 0,
+    Enemy = 1,
+    Asteroid = 2,
+    Bullet = 3,
+}
+
+#[derive(Copy, Clone)]
+struct SpriteInstance{
+    offset: [f32; 2],
+    rots: [f32; 2],
+    layer: f32,
+    scale: f32,
+}
+implement_vertex!(SpriteInstance, offset, rots, layer, scale);
+
+impl SpriteInstance{
+    fn new(sprite: Sprite, offset: [f32; 2], rots: [f32; 2]) -> Self{
+        SpriteInstance{
+            offset,
+            rots,
+            layer: sprite as u32 as f32,
+            scale: 1.0,
+        }
+    }
+
+    // Asteroid fragments reuse the full size sprite drawn smaller
+    fn scaled(self, scale: f32) -> Self{
+        SpriteInstance{scale, ..self}
+    }
+}
+
+// Times per second an invulnerable ship flicks between drawn and hidden
+const BLINK_RATE: f32 = 8.0;
+
+// Half the diagonal of the largest unscaled sprite quad, a ghost further off screen than this can't be seen
+const SPRITE_EXTENT: f32 = 1.0;
+
+// Queues a sprite plus a ghost copy on the far side of any wrapping edge it straddles,
+// so what is drawn near an edge matches what collides across it
+fn push_wrapped(sprites: &mut Vec<SpriteInstance>, sprite: SpriteInstance, bounds: &WorldBounds){
+    sprites.push(sprite);
+    for &shift_x in [0.0, bounds.width(), -bounds.width()].iter(){
+        for &shift_y in [0.0, bounds.height(), -bounds.height()].iter(){
+            if shift_x == 0.0 && shift_y == 0.0{
+                continue;
+            }
+            let offset = [sprite.offset[0] + shift_x, sprite.offset[1] + shift_y];
+            let extent = SPRITE_EXTENT * sprite.scale;
+            if offset[0].abs() - extent < bounds.x && offset[1].abs() - extent < bounds.y{
+                sprites.push(SpriteInstance{offset, ..sprite});
+            }
+        }
+    }
+}
+
+// Sine and cosine of a rotation, as the sprite shader expects them
+fn rotation_vector(angle: &Rotation) -> [f32; 2]{
+    [(angle.0/180.0 * std::f32::consts::PI).sin(), (angle.0/180.0 * std::f32::consts::PI).cos()]
+}
+
+fn load_sprite(png: &[u8]) -> glium::texture::RawImage2d<'static, u8>{
+    let image = image::load(Cursor::new(png), image::ImageFormat::Png).unwrap().to_rgba();
+    let image_dimensions = image.dimensions();
+    glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions)
+}
+
+//TEXT
+// 5x7 glyphs, top row first, '#' is a lit pixel. Lowercase letters draw as uppercase
+const FONT: [(char, [&str; 7]); 45] = [
+    (' ', ["     ", "     ", "     ", "     ", "     ", "     ", "     "]),
+    ('A', [" ### ", "#   #", "#   #", "#####", "#   #", "#   #", "#   #"]),
+    ('B', ["#### ", "#   #", "#   #", "#### ", "#   #", "#   #", "#### "]),
+    ('C', [" ### ", "#   #", "#    ", "#    ", "#    ", "#   #", " ### "]),
+    ('D', ["#### ", "#   #", "#   #", "#   #", "#   #", "#   #", "#### "]),
+    ('E', ["#####", "#    ", "#    ", "#### ", "#    ", "#    ", "#####"]),
+    ('F', ["#####", "#    ", "#    ", "#### ", "#    ", "#    ", "#    "]),
+    ('G', [" ### ", "#   #", "#    ", "# ###", "#   #", "#   #", " ####"]),
+    ('H', ["#   #", "#   #", "#   #", "#####", "#   #", "#   #", "#   #"]),
+    ('I', [" ### ", "  #  ", "  #  ", "  #  ", "  #  ", "  #  ", " ### "]),
+    ('J', ["  ###", "   # ", "   # ", "   # ", "   # ", "#  # ", " ##  "]),
+    ('K', ["#   #", "#  # ", "# #  ", "##   ", "# #  ", "#  # ", "#   #"]),
+    ('L', ["#    ", "#    ", "#    ", "#    ", "#    ", "#    ", "#####"]),
+    ('M', ["#   #", "## ##", "# # #", "# # #", "#   #", "#   #", "#   #"]),
+    ('N', ["#   #", "#   #", "##  #", "# # #", "#  ##", "#   #", "#   #"]),
+    ('O', [" ### ", "#   #", "#   #", "#   #", "#   #", "#   #", " ### "]),
+    ('P', ["#### ", "#   #", "#   #", "#### ", "#    ", "#    ", "#    "]),
+    ('Q', [" ### ", "#   #", "#   #", "#   #", "# # #", "#  # ", " ## #"]),
+    ('R', ["#### ", "#   #", "#   #", "#### ", "# #  ", "#  # ", "#   #"]),
+    ('S', [" ####", "#    ", "#    ", " ### ", "    #", "    #", "#### "]),
+    ('T', ["#####", "  #  ", "  #  ", "  #  ", "  #  ", "  #  ", "  #  "]),
+    ('U', ["#   #", "#   #", "#   #", "#   #", "#   #", "#   #", " ### "]),
+    ('V', ["#   #", "#   #", "#   #", "#   #", "#   #", " # # ", "  #  "]),
+    ('W', ["#   #", "#   #", "#   #", "# # #", "# # #", "# # #", " # # "]),
+    ('X', ["#   #", "#   #", " # # ", "  #  ", " # # ", "#   #", "#   #"]),
+    ('Y', ["#   #", "#   #", " # # ", "  #  ", "  #  ", "  #  ", "  #  "]),
+    ('Z', ["#####", "    #", "   # ", "  #  ", " #   ", "#    ", "#####"]),
+    ('0', [" ### ", "#   #", "#  ##", "# # #", "##  #", "#   #", " ### "]),
+    ('1', ["  #  ", " ##  ", "  #  ", "  #  ", "  #  ", "  #  ", " ### "]),
+    ('2', [" ### ", "#   #", "    #", "   # ", "  #  ", " #   ", "#####"]),
+    ('3', ["#####", "   # ", "  #  ", "   # ", "    #", "#   #", " ### "]),
+    ('4', ["   # ", "  ## ", " # # ", "#  # ", "#####", "   # ", "   # "]),
+    ('5', ["#####", "#    ", "#### ", "    #", "    #", "#   #", " ### "]),
+    ('6', ["  ## ", " #   ", "#    ", "#### ", "#   #", "#   #", " ### "]),
+    ('7', ["#####", "    #", "   # ", "  #  ", " #   ", " #   ", " #   "]),
+    ('8', [" ### ", "#   #", "#   #", " ### ", "#   #", "#   #", " ### "]),
+    ('9', [" ### ", "#   #", "#   #", " ####", "    #", "   # ", " ##  "]),
+    (':', ["     ", "  #  ", "  #  ", "     ", "  #  ", "  #  ", "     "]),
+    ('-', ["     ", "     ", "     ", "#####", "     ", "     ", "     "]),
+    (',', ["     ", "     ", "     ", "     ", "  ## ", "   # ", "  #  "]),
+    ('.', ["     ", "     ", "     ", "     ", "     ", " ##  ", " ##  "]),
+    ('!', ["  #  ", "  #  ", "  #  ", "  #  ", "  #  ", "     ", "  #  "]),
+    ('?', [" ### ", "#   #", "    #", "   # ", "  #  ", "     ", "  #  "]),
+    ('(', ["   # ", "  #  ", " #   ", " #   ", " #   ", "  #  ", "   # "]),
+    (')', [" #   ", "  #  ", "   # ", "   # ", "   # ", "  #  ", " #   "]),
+];
+
+// Pixels per side of a glyph's texture layer, the glyph sits one column in with the bottom row left blank
+const GLYPH_CELL: u32 = 8;
+// Glyphs step 6 of their 8 pixels, leaving one blank column between neighbours
+const GLYPH_ADVANCE: f32 = 6.0 / 8.0;
+
+// One white-on-transparent texture layer per FONT entry, in the same order
+fn build_font() -> Vec<glium::texture::RawImage2d<'static, u8>>{
+    FONT.iter().map(|(_, rows)| {
+        let mut pixels = vec![0u8; (GLYPH_CELL * GLYPH_CELL * 4) as usize];
+        for (y, row) in rows.iter().enumerate(){
+            for (x, pixel) in row.chars().enumerate(){
+                if pixel == '#'{
+                    // The HUD projection puts the first texture row at the top of the quad
+                    let start = ((y as u32 * GLYPH_CELL + x as u32 + 1) * 4) as usize;
+                    pixels[start..start + 4].copy_from_slice(&[255, 255, 255, 255]);
+                }
+            }
+        }
+        glium::texture::RawImage2d::from_raw_rgba(pixels, (GLYPH_CELL, GLYPH_CELL))
+    }).collect()
+}
+
+// Unknown characters draw as '?'
+fn glyph_layer(c: char) -> usize{
+    let c = c.to_ascii_uppercase();
+    FONT.iter().position(|(glyph, _)| *glyph == c)
+        .unwrap_or_else(|| FONT.iter().position(|(glyph, _)| *glyph == '?').unwrap())
+}
+
+fn text_width(text: &str, size: f32) -> f32{
+    text.chars().count() as f32 * size * GLYPH_ADVANCE
+}
+
+// Queues a line of text with its top left corner at (x, y) in HUD pixels
+fn push_text(glyphs: &mut Vec<SpriteInstance>, text: &str, x: f32, y: f32, size: f32){
+    for (i, c) in text.chars().enumerate(){
+        if c == ' '{
+            continue;
+        }
+        glyphs.push(SpriteInstance{
+            offset: [x + i as f32 * size * GLYPH_ADVANCE + size * 0.5, y + size * 0.5],
+            rots: [0.0, 1.0],
+            layer: glyph_layer(c) as f32,
+            scale: size,
+        });
+    }
+}
+
+// Window pixels with the origin at the top left and y going down, regardless of the arena's letterboxing
+fn hud_projection((width, height): (u32, u32)) -> [[f32; 4]; 4]{
+    [
+        [2.0 / width as f32, 0.0, 0.0, 0.0],
+        [0.0, -2.0 / height as f32, 0.0, 0.0],
+        [0.0, 0.0, -0.1, 0.0],
+        [-1.0, 1.0, 0.0, 1.0],
+    ]
+}
+
+// Text size in pixels, whole multiples of the glyph cell keep the pixels crisp
+fn hud_text_size((width, height): (u32, u32)) -> f32{
+    ((width.min(height) / 320).max(1) * GLYPH_CELL) as f32
+}
+
+// Each ship's health, lives and score in its top corner, the wave and round timer between them,
+// and the match message across the middle whenever play is stopped
+fn build_hud(world: &World, glyphs: &mut Vec<SpriteInstance>, (width, height): (u32, u32)){
+    let ships = ReadComp::<Ship>::get_data(world);
+    let healths = ReadComp::<Health>::get_data(world);
+    let lives = ReadComp::<Lives>::get_data(world);
+    let scores = ReadComp::<Score>::get_data(world);
+    let waves = Read::<Waves>::get_data(world);
+    let time = Read::<Time>::get_data(world);
+    let game = Read::<Match>::get_data(world);
+
+    let size = hud_text_size((width, height));
+    let line = size * 1.25;
+    let margin = size;
+    let right = width as f32 - margin;
+
+    for (ship, health, ship_lives, score) in (&ships, &healths, &lives, &scores).join(){
+        let mut lines = vec![
+            ship_name(ship.0),
+            format!("HP {}  LIVES {}", health.0.max(0), ship_lives.0),
+            format!("SCORE {}", score.points),
+        ];
+        if score.combo > 1{
+            lines.push(format!("COMBO X{}", score.combo));
+        }
+        // Ships alternate between the left and right corner, any past the first two stack below
+        let top = margin + (ship.0 / 2) as f32 * line * 5.0;
+        for (i, text) in lines.iter().enumerate(){
+            let x = if ship.0 % 2 == 0 {margin} else {right - text_width(text, size)};
+            push_text(glyphs, text, x, top + i as f32 * line, size);
+        }
+    }
+
+    if game.state != GameState::Title{
+        let seconds = ((time.tick - game.round_start) as f64 * time.delta) as u64;
+        let status = [
+            format!("WAVE {}", waves.wave),
+            format!("{:02}:{:02}", seconds / 60, seconds % 60),
+        ];
+        for (i, text) in status.iter().enumerate(){
+            push_text(glyphs, text, (width as f32 - text_width(text, size)) * 0.5, margin + i as f32 * line, size);
+        }
+    }
+
+    if game.state != GameState::Playing{
+        let message = game.message();
+        let lines: Vec<&str> = message.split(", ").collect();
+        let top = (height as f32 - lines.len() as f32 * line) * 0.5;
+        for (i, text) in lines.iter().enumerate(){
+            push_text(glyphs, text, (width as f32 - text_width(text, size)) * 0.5, top + i as f32 * line, size);
+        }
+    }
+}
+
+
+// Everything a match needs except the ships, which each round spawns fresh
+fn setup_world(seed: u64, bounds: WorldBounds, config: Config, enemy_source: ControlSource) -> World{
+    let mut world = World::new();
+    register_components(&mut world);
+
+    world.insert(bounds);
+    world.insert(Time::new(TICK_RATE));
+    world.insert(GameRng::new(seed));
+    world.insert(CollisionGrid::new());
+    world.insert(DamageTable::default());
+    world.insert(CollisionEvents{events: Vec::new()});
+    world.insert(DamageEvents{events: Vec::new()});
+    world.insert(ShotQueue{shots: Vec::new()});
+    world.insert(config.controls);
+    world.insert(Waves::new(config.waves));
+    world.insert(GamepadInputs{pads: Vec::new()});
+    world.insert(ControlInputs::new());
+    world.insert(EntityStorage::new());
+    world.insert(Match::new(enemy_source));
+
+    world
+}
+
+fn spawn_ships(world: &World){
+    let bound_y = Read::<WorldBounds>::get_data(world).y;
+    let enemy_source = Read::<Match>::get_data(world).enemy_source;
+    let mut ents = Write::<EntityStorage>::get_data(world);
+    let mut players = WriteComp::<Player>::get_data(world);
+    let mut enemies = WriteComp::<Enemy>::get_data(world);
+    let mut health = WriteComp::<Health>::get_data(world);
+    let mut positions = WriteComp::<Position>::get_data(world);
+    let mut vels = WriteComp::<Velocity>::get_data(world);
+    let mut radius = WriteComp::<Radius>::get_data(world);
+    let mut angles = WriteComp::<Rotation>::get_data(world);
+    let mut angle_vel = WriteComp::<RotationVelocity>::get_data(world);
+    let mut colliders = WriteComp::<Collider>::get_data(world);
+    let mut weapons = WriteComp::<Weapon>::get_data(world);
+    let mut ships = WriteComp::<Ship>::get_data(world);
+    let mut controllers = WriteComp::<Controller>::get_data(world);
+    let mut actions = WriteComp::<Actions>::get_data(world);
+    let mut ai_states = WriteComp::<AiState>::get_data(world);
+    let mut lives = WriteComp::<Lives>::get_data(world);
+    let mut scores = WriteComp::<Score>::get_data(world);
+    
+    //Make the player
+    ents.create_entity()
+        .add(&mut players, Player{})
+        .add(&mut health, Health(SHIP_HEALTH))
+        .add(&mut positions, Position{x: 0.0, y: -bound_y + 0.5})
+        .add(&mut vels, Velocity{x: 0.0, y: 0.0})
+        .add(&mut radius, Radius(0.5))
+        .add(&mut angles, Rotation(0.0))
+        .add(&mut angle_vel, RotationVelocity(0.0))
+        .add(&mut colliders, Collider::ship(PLAYER_SHIP))
+        .add(&mut weapons, Weapon::default())
+        .add(&mut ships, Ship(PLAYER_SHIP))
+        .add(&mut controllers, Controller{source: ControlSource::Human(0)})
+        .add(&mut actions, Actions::default())
+        .add(&mut ai_states, AiState::default())
+        .add(&mut lives, Lives(STARTING_LIVES))
+        .add(&mut scores, Score::new());
+
+        
+    //Make the enemies
+    ents.create_entity()
+        .add(&mut enemies, Enemy{})
+        .add(&mut health, Health(SHIP_HEALTH))
+        .add(&mut positions, Position{x: 0.0, y: bound_y - 0.5})
+        .add(&mut vels, Velocity{x: 0.0, y: 0.0})
+        .add(&mut radius, Radius(0.5))
+        .add(&mut angles, Rotation(180.0))
+        .add(&mut angle_vel, RotationVelocity(0.0))
+        .add(&mut colliders, Collider::ship(ENEMY_SHIP))
+        .add(&mut weapons, Weapon::default())
+        .add(&mut ships, Ship(ENEMY_SHIP))
+        .add(&mut controllers, Controller{source: enemy_source})
+        .add(&mut actions, Actions::default())
+        .add(&mut ai_states, AiState::default())
+        .add(&mut lives, Lives(STARTING_LIVES))
+        .add(&mut scores, Score::new());
+    
+    drop(ents);
+    drop(players);
+    drop(enemies);
+    drop(health);
+    drop(positions);
+    drop(vels);
+    drop(radius);
+    drop(angles);
+    drop(angle_vel);
+    drop(colliders);
+    drop(weapons);
+    drop(ships);
+    drop(controllers);
+    drop(actions);
+    drop(ai_states);
+    drop(lives);
+    drop(scores);
+}
+
+// Despawns every entity and empties the per-tick queues, ready for a fresh round
+fn clear_world(world: &World){
+    let ents = Read::<EntityStorage>::get_data(world);
+    let positions = ReadComp::<Position>::get_data(world);
+    let ships = ReadComp::<Ship>::get_data(world);
+    let mut deads = WriteComp::<Dead>::get_data(world);
+    let mut everything = Vec::new();
+    for (position, entity) in (&positions, ents.deref()).join(){
+        everything.push(entity.clone());
+    }
+    for (ship, entity) in (&ships, ents.deref()).join(){
+        everything.push(entity.clone());
+    }
+    for entity in everything.drain(..){
+        entity.add(&mut deads, Dead{});
+    }
+    drop(ents);
+    drop(positions);
+    drop(ships);
+    drop(deads);
+    despawn_dead(world);
+
+    let mut waves = Write::<Waves>::get_data(world);
+    waves.wave = 0;
+    waves.next_wave_in = 0.0;
+    drop(waves);
+    Write::<ShotQueue>::get_data(world).shots.clear();
+    Write::<CollisionEvents>::get_data(world).events.clear();
+    Write::<DamageEvents>::get_data(world).events.clear();
+}
 
-            new_bullet_position = match inputs.key_status(VirtualKeyCode::S){
-                KeyStatus::Pressed => {
-                    Some((Position{
-                        x: position.x + direction.0 * 1.0,
-                        y: position.y + direction.1 * 1.0,
-                    },
-                    Velocity{
-                        x: vel.x + direction.0 * 10.0,
-                        y: vel.y + direction.1 * 10.0,
-                    }
-                    ))
-                },
-                _ => None,
-            }
-        }
-        if let Some((pos, vel)) = new_bullet_position{
-            ents.create_entity()
-                .add(&mut positions, pos)
-                .add(&mut vels, vel)
-                .add(&mut bullets, Bullet{})
-                .add(&mut radii, Radius(0.25))
-                .add(&mut healths, Health(1));
-        }
+fn start_round(world: &World){
+    clear_world(world);
+    spawn_ships(world);
+    let tick = Read::<Time>::get_data(world).tick;
+    let mut game = Write::<Match>::get_data(world);
+    let ships = ReadComp::<Ship>::get_data(world);
+    let healths = ReadComp::<Health>::get_data(world);
+    for (ship, _) in (&ships, &healths).join(){
+        game.track_ship(ship.0);
+    }
+    drop(ships);
+    drop(healths);
+    game.round += 1;
+    game.round_start = tick;
+    game.state = GameState::Playing;
+    println!("{}", game.message());
+}
 
-        
-        let mut new_bullet_position = None;
-        for (vel, a_vel, rot, enemy, position) in (&mut vels, &mut a_vels, &rots, &enemies, &positions).join(){
-            let mut turn_val = 0.0;
-            match inputs.key_status(VirtualKeyCode::Left){
-                KeyStatus::Up => {},
-                _ => {turn_val += 180.0;},
-            }
-            match inputs.key_status(VirtualKeyCode::Right){
-                KeyStatus::Up => {},
-                _ => {turn_val -= 180.0;},
-            }
-            if turn_val == 0.0 && a_vel.0.abs() != 0.0{
-                turn_val = -a_vel.0.signum() * 180.0;
+// Moves the match along on this tick's menu keys, called every tick before gameplay runs
+fn update_match(world: &World){
+    let (start, pause) = {
+        let inputs = Read::<ControlInputs>::get_data(world);
+        let pressed = |key| inputs.key_status(key) == KeyStatus::Pressed;
+        (pressed(VirtualKeyCode::Return), pressed(VirtualKeyCode::P) || pressed(VirtualKeyCode::Escape))
+    };
+    let mut game = Write::<Match>::get_data(world);
+    let state = game.state;
+    match state{
+        GameState::Title | GameState::RoundOver{..} if start => {
+            if state == GameState::Title{
+                game.wins.clear();
+                game.scores.clear();
+                game.round = 0;
             }
-            a_vel.0 += turn_val * time.delta as f32;
+            drop(game);
+            start_round(world);
+        },
+        GameState::GameOver{..} if start => {
+            game.state = GameState::Title;
+            println!("{}", game.message());
+        },
+        GameState::Playing if pause => {
+            game.state = GameState::Paused;
+            println!("{}", game.message());
+        },
+        GameState::Paused if pause => {
+            game.state = GameState::Playing;
+            println!("{}", game.message());
+        },
+        _ => {},
+    }
+}
 
-            let mut forward_val = 0.0;
-            match inputs.key_status(VirtualKeyCode::Up){
-                KeyStatus::Up => {},
-                _ => {forward_val = 1.0},
-            }
-            let direction = (
-                (-rot.0 * std::f32::consts::PI/180.0).sin() ,
-                (rot.0 * std::f32::consts::PI/180.0).cos()
-            );
-            vel.x += forward_val * direction.0 * time.delta as f32;
-            vel.y += forward_val * direction.1 * time.delta as f32;
+fn print_summary(world: &World){
+    let ents = Read::<EntityStorage>::get_data(world);
+    let players = ReadComp::<Player>::get_data(world);
+    let enemies = ReadComp::<Enemy>::get_data(world);
+    let asteroids = ReadComp::<Asteroid>::get_data(world);
+    let bullets = ReadComp::<Bullet>::get_data(world);
+    let positions = ReadComp::<Position>::get_data(world);
+    let healths = ReadComp::<Health>::get_data(world);
+    let rng = Read::<GameRng>::get_data(world);
+    let waves = Read::<Waves>::get_data(world);
+    let game = Read::<Match>::get_data(world);
+    let lives = ReadComp::<Lives>::get_data(world);
+    let scores = ReadComp::<Score>::get_data(world);
+
+    println!("Seed: {}", rng.seed);
+    println!("{}", game.message());
+    println!("Wave: {}", waves.wave);
+
+    // A ship waiting to respawn has no position
+    for (player, health, lives, score) in (&players, &healths, &lives, &scores).join(){
+        println!("Player: health {}, lives {}, score {}", health.0, lives.0, score.points);
+    }
+    for (player, position) in (&players, &positions).join(){
+        println!("Player: at ({}, {})", position.x, position.y);
+    }
+    for (enemy, health, lives, score) in (&enemies, &healths, &lives, &scores).join(){
+        println!("Enemy: health {}, lives {}, score {}", health.0, lives.0, score.points);
+    }
+    for (enemy, position) in (&enemies, &positions).join(){
+        println!("Enemy: at ({}, {})", position.x, position.y);
+    }
 
-            new_bullet_position = match inputs.key_status(VirtualKeyCode::Down){
-                KeyStatus::Pressed => {
-                    Some((Position{
-                        x: position.x + direction.0 * 1.0,
-                        y: position.y + direction.1 * 1.0,
-                    },
-                    Velocity{
-                        x: vel.x + direction.0 * 10.0,
-                        y: vel.y + direction.1 * 10.0,
-                    }
-                    ))
-                },
-                _ => None,
-            }
-        }
-        if let Some((pos, vel)) = new_bullet_position{
-            ents.create_entity()
-                .add(&mut positions, pos)
-                .add(&mut vels, vel)
-                .add(&mut bullets, Bullet{})
-                .add(&mut radii, Radius(0.25))
-                .add(&mut healths, Health(1));
-        }
+    let mut asteroid_count = 0;
+    for (asteroid, entity) in (&asteroids, ents.deref()).join(){
+        asteroid_count += 1;
+    }
+    let mut bullet_count = 0;
+    for (bullet, entity) in (&bullets, ents.deref()).join(){
+        bullet_count += 1;
     }
+    println!("Asteroids: {}", asteroid_count);
+    println!("Bullets: {}", bullet_count);
 }
 
-fn collision_check(rad_one: &Radius, pos_one: &Position, rad_two: &Radius, pos_two: &Position) -> bool{
-    (pos_two.x - pos_one.x).powi(2) + (pos_two.y - pos_one.y).powi(2) <= (rad_one.0 + rad_two.0).powi(2)
+struct Options{
+    headless: bool,
+    ticks: Option<u64>,
+    seed: Option<u64>,
+    record: Option<String>,
+    replay: Option<String>,
+    arena: Option<(f32, f32)>,
+    config: String,
+    ai: Option<Difficulty>,
 }
 
-use std::ops::Deref;
-pub struct DamagerCollisionCheck;
-impl<'d, 'w: 'd> System<'d, 'w, World> for DamagerCollisionCheck{
-    type SystemData = (
-        ReadComp<'d, Radius>,
-        ReadComp<'d, Position>,
-        WriteComp<'d, Health>,
-        Read<'d, EntityStorage>,
-    );
+const DEFAULT_ARENA: (f32, f32) = (20.0, 20.0);
 
-    fn run(&self, (radii, positions, mut healths, ents): Self::SystemData) {
-        //Check Bullet Collisions
-        for (pos_one, rad_one, health, ent_one) in (&positions, &radii, &mut healths, ents.deref()).join(){
-            for(pos_two, rad_two, ent_two) in (&positions, &radii, ents.deref()).join(){
-                if collision_check(rad_one, pos_one, rad_two, pos_two) && ent_one != ent_two{
-                    health.0 -= 1;
-                    break;
-                }
-            }
-        }
+// Parses a full arena size such as "32x18"
+fn parse_arena(arena: &str) -> Option<(f32, f32)>{
+    let mut split = arena.split('x');
+    let width: f32 = split.next()?.parse().ok()?;
+    let height: f32 = split.next()?.parse().ok()?;
+    if split.next().is_some() || width <= 0.0 || height <= 0.0{
+        return None;
     }
+    Some((width, height))
 }
 
-pub struct DestroyZeroHealth;
-impl<'d, 'w: 'd> System<'d, 'w, World> for DestroyZeroHealth{
-    type SystemData = (
-        WriteComp<'d, Player>,
-        WriteComp<'d, Enemy>,
-        WriteComp<'d, Velocity>,
-        WriteComp<'d, Position>,
-        WriteComp<'d, Radius>,
-        WriteComp<'d, Health>,
-        WriteComp<'d, Rotation>,
-        WriteComp<'d, RotationVelocity>,
-        WriteComp<'d, Asteroid>,
-        WriteComp<'d, Bullet>,
-        Write<'d, EntityStorage>,
-    );
+fn parse_args() -> Options{
+    let mut options = Options{
+        headless: false,
+        ticks: None,
+        seed: None,
+        record: None,
+        replay: None,
+        arena: None,
+        config: DEFAULT_CONFIG_PATH.to_string(),
+        ai: None,
+    };
 
-    fn run(&self, (mut players, mut enemies, mut velocities, mut positions, mut radii, mut healths, mut rotations, mut rotationvels, mut asteroids, mut bullets, mut ents): Self::SystemData) {
-        
-        let mut bullets_to_delete = Vec::new();
-        for (bullet, health, entity) in (&bullets, &healths, ents.deref()).join(){
-            if health.0 <= 0{
-                bullets_to_delete.push(entity.clone());
-            }
-        }
-        for bullet in bullets_to_delete.drain(..){
-            bullet
-                .remove(&mut healths)
-                .remove(&mut positions)
-                .remove(&mut velocities)
-                .remove(&mut radii)
-                .remove(&mut bullets);
-            ents.delete_entity(&bullet);
-        }
-        
-        let mut asteroids_to_delete = Vec::new();
-        for (asteroid, health, entity) in (&asteroids, &healths, ents.deref()).join(){
-            if health.0 <= 0{
-                asteroids_to_delete.push(entity.clone());
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next(){
+        match arg.as_str(){
+            "--headless" => {options.headless = true;},
+            "--ticks" => {
+                options.ticks = Some(args.next()
+                    .and_then(|ticks| ticks.parse().ok())
+                    .expect("--ticks expects a number of simulation ticks"));
+            },
+            "--seed" => {
+                options.seed = Some(args.next()
+                    .and_then(|seed| seed.parse().ok())
+                    .expect("--seed expects an unsigned integer"));
+            },
+            "--record" => {
+                options.record = Some(args.next().expect("--record expects a file path"));
+            },
+            "--replay" => {
+                options.replay = Some(args.next().expect("--replay expects a file path"));
+            },
+            "--arena" => {
+                options.arena = Some(args.next()
+                    .and_then(|arena| parse_arena(&arena))
+                    .expect("--arena expects a size like 20x20"));
+            },
+            "--config" => {
+                options.config = args.next().expect("--config expects a file path");
+            },
+            "--ai" => {
+                options.ai = Some(args.next()
+                    .and_then(|difficulty| Difficulty::parse(&difficulty))
+                    .expect("--ai expects easy, normal or hard"));
+            },
+            _ => {
+                eprintln!("Unknown argument: {}", arg);
+                eprintln!("Usage: SmolShooter [--headless] [--ticks N] [--seed N] [--record FILE] [--replay FILE] [--arena WxH] [--config FILE] [--ai easy|normal|hard]");
+                std::process::exit(1);
             }
         }
-        for asteroid in asteroids_to_delete.drain(..){
-            asteroid
-                .remove(&mut healths)
-                .remove(&mut positions)
-                .remove(&mut velocities)
-                .remove(&mut radii)
-                .remove(&mut rotations)
-                .remove(&mut rotationvels)
-                .remove(&mut asteroids);
-                ents.delete_entity(&asteroid);
-        }
-        
-        let mut players_to_delete = Vec::new();
-        for (player, health, entity) in (&players, &healths, ents.deref()).join(){
-            if health.0 <= 0{
-                players_to_delete.push(entity.clone());
-            }
-        }
-        for player in players_to_delete.drain(..){
-            player
-                .remove(&mut players)
-                .remove(&mut healths)
-                .remove(&mut positions)
-                .remove(&mut velocities)
-                .remove(&mut radii)
-                .remove(&mut rotations)
-                .remove(&mut rotationvels);
-                ents.delete_entity(&player);
-        }
-        
-        let mut enemies_to_delete = Vec::new();
-        for (enemy, health, entity) in (&enemies, &healths, ents.deref()).join(){
-            if health.0 <= 0{
-                enemies_to_delete.push(entity.clone());
-            }
-        }
-        for enemy in enemies_to_delete.drain(..){
-            enemy
-                .remove(&mut players)
-                .remove(&mut healths)
-                .remove(&mut positions)
-                .remove(&mut velocities)
-                .remove(&mut radii)
-                .remove(&mut rotations)
-                .remove(&mut rotationvels);
-                ents.delete_entity(&enemy);
-        }
     }
+    options
 }
 
-//RENDER STUFF
-#[derive(Copy, Clone)]
-struct Vertex {
-    position: [f32; 2],
-    uv: [f32; 2],
+// Every system in tick order. A macro rather than a function so main and the tests share one list
+// without spelling out the scheduler's type
+macro_rules! build_scheduler{
+    () => {{
+        let mut scheduler = SystemScheduler::new(Arc::new(ThreadPoolBuilder::new().num_threads(4).build().unwrap()));
+        scheduler.add(UpdateTime{}, "update_time", vec![]);
+        scheduler.add(ReadControllers{}, "read_controllers", vec![]);
+        scheduler.add(AiControl{}, "ai_control", vec!["update_time", "read_controllers"]);
+        scheduler.add(ApplyControls{}, "apply_controls", vec!["update_time", "ai_control"]);
+        scheduler.add(SpawnShots{}, "spawn_shots", vec!["apply_controls"]);
+        scheduler.add(ApplyVelocities{}, "update_positions", vec!["update_time", "spawn_shots"]);
+        scheduler.add(ApplyRotationVelocities{}, "update_angles", vec!["update_time", "apply_controls"]);
+        scheduler.add(BuildCollisionGrid{}, "build_grid", vec!["update_positions"]);
+        scheduler.add(CollisionCheck{}, "collision_check", vec!["build_grid"]);
+        scheduler.add(DamagerCollisionCheck{}, "damage_check", vec!["collision_check"]);
+        scheduler.add(ExpireLifetimes{}, "expire_lifetimes", vec!["update_positions"]);
+        scheduler.add(LoseLife{}, "lose_life", vec!["damage_check", "expire_lifetimes"]);
+        scheduler.add(DestroyZeroHealth{}, "destroy_zero", vec!["lose_life"]);
+        scheduler.add(AwardScore{}, "award_score", vec!["damage_check"]);
+        scheduler.add(CheckRoundOver{}, "check_round_over", vec!["destroy_zero", "award_score"]);
+        scheduler.add(SplitAsteroids{}, "split_asteroids", vec!["check_round_over"]);
+        scheduler.add(SpawnWaves{}, "spawn_waves", vec!["split_asteroids"]);
+        scheduler.add(RespawnShips{}, "respawn_ships", vec!["spawn_waves"]);
+        scheduler.add(ExpireInvulnerability{}, "expire_invulnerability", vec!["respawn_ships"]);
+        scheduler
+    }};
 }
-implement_vertex!(Vertex, position, uv);
 
+// Runs one simulation tick, returns false when a replay has just run out of input.
+// run_systems runs the scheduler, which is passed in as a closure to keep its type out of the signature.
+fn simulate_tick(world: &World, replay: &mut Option<InputReplay>, recorder: &mut Option<InputRecorder>, run_systems: impl FnOnce()) -> bool{
+    if let Some(input_replay) = replay.as_mut(){
+        let mut inputs = Write::<ControlInputs>::get_data(world);
+        let mut pads = Write::<GamepadInputs>::get_data(world);
+        match input_replay.next_tick(&mut inputs, &mut pads){
+            Ok(true) => {},
+            Ok(false) => {
+                println!("Replay finished");
+                *replay = None;
+                return false;
+            },
+            Err(err) => {
+                eprintln!("Replay is damaged: {}", err);
+                std::process::exit(1);
+            },
+        }
+    } else {
+        Write::<ControlInputs>::get_data(world).sample();
+    }
+    if let Some(input_recorder) = recorder.as_mut(){
+        let recorded = input_recorder.record(
+            &Read::<ControlInputs>::get_data(world),
+            &Read::<GamepadInputs>::get_data(world)
+        );
+        if let Err(err) = recorded{
+            eprintln!("Stopped recording: {}", err);
+            *recorder = None;
+        }
+    }
+
+    // Menus and pauses still sample and record input, only gameplay stops
+    update_match(world);
+    if Read::<Match>::get_data(world).state == GameState::Playing{
+        run_systems();
+        despawn_dead(world);
+    }
+    true
+}
 
 fn main() {
+    let options = parse_args();
+
+    let mut replay = options.replay.as_ref().map(|path| {
+        InputReplay::open(path).unwrap_or_else(|err| {
+            eprintln!("Could not open replay {}: {}", path, err);
+            std::process::exit(1);
+        })
+    });
+
+    // A replay only reproduces the match it was recorded from
+    let seed = match (&replay, options.seed){
+        (Some(replay), Some(seed)) if replay.header.seed != seed => {
+            eprintln!("Ignoring --seed {}, the replay was recorded with seed {}", seed, replay.header.seed);
+            replay.header.seed
+        },
+        (Some(replay), _) => replay.header.seed,
+        (None, Some(seed)) => seed,
+        (None, None) => rand::thread_rng().gen(),
+    };
+    println!("Seed: {}", seed);
+
+    // Nobody is at the keyboard to leave the title screen of a headless run
+    let skip_title = match &replay{
+        Some(replay) => replay.header.skip_title,
+        None => options.headless,
+    };
+
+    let arena = match (&replay, options.arena){
+        (Some(replay), Some(arena)) if replay.header.arena != arena => {
+            eprintln!("Ignoring --arena, the replay was recorded in a {}x{} arena", replay.header.arena.0, replay.header.arena.1);
+            replay.header.arena
+        },
+        (Some(replay), _) => replay.header.arena,
+        (None, arena) => arena.unwrap_or(DEFAULT_ARENA),
+    };
+
+    // The enemy ship is the second player unless the computer is flying it
+    let requested_source = match options.ai{
+        Some(difficulty) => ControlSource::Ai(difficulty),
+        None => ControlSource::Human(1),
+    };
+    let enemy_source = match &replay{
+        Some(replay) if options.ai.is_some() && replay.header.enemy_source != requested_source => {
+            eprintln!("Ignoring --ai, the replay was recorded with the enemy flown by {:?}", replay.header.enemy_source);
+            replay.header.enemy_source
+        },
+        Some(replay) => replay.header.enemy_source,
+        None => requested_source,
+    };
+    let humans = human_ships(enemy_source);
+
+    let mut config = load_config(&options.config, humans).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+    // A replay plays by the config it was recorded with, whatever the file says now
+    if let Some(replay) = &replay{
+        config.controls = replay.header.controls.clone();
+        config.waves = replay.header.waves.clone();
+    }
+
+    let mut recorder = options.record.as_ref().map(|path| {
+        let header = RecordingHeader{
+            version: RECORDING_VERSION,
+            seed,
+            skip_title,
+            arena,
+            enemy_source,
+            controls: config.controls.clone(),
+            waves: config.waves.clone(),
+        };
+        InputRecorder::create(path, &header).unwrap_or_else(|err| {
+            eprintln!("Could not create recording {}: {}", path, err);
+            std::process::exit(1);
+        })
+    });
+
+    // A reload partway through would make the recording play out differently from the match it was made from
+    let config_locked = recorder.is_some() || replay.is_some();
+
+    // SmolECS Setup
+    let world = setup_world(seed, WorldBounds::new(arena.0 / 2.0, arena.1 / 2.0), config, enemy_source);
+    if skip_title{
+        start_round(&world);
+    } else {
+        println!("{}", Read::<Match>::get_data(&world).message());
+    }
+
+    let scheduler = build_scheduler!();
+
+    let mut run_tick = || simulate_tick(&world, &mut replay, &mut recorder, || scheduler.run(&world));
+
+    // Headless simulation, no window or GPU required
+    if options.headless{
+        // Replays run to the end of the recording unless told otherwise
+        let max_ticks = match (options.ticks, &options.replay){
+            (Some(ticks), _) => ticks,
+            (None, Some(_)) => std::u64::MAX,
+            (None, None) => 600,
+        };
+        let mut ticks = 0;
+        while ticks < max_ticks && run_tick(){
+            ticks += 1;
+            // A replay can carry the keys that start the next round, otherwise nothing would happen after this one
+            if options.replay.is_none() && Read::<Match>::get_data(&world).state != GameState::Playing{
+                println!("Stopping, the round is over and nobody is there to start the next one");
+                break;
+            }
+        }
+        // Time only advances while playing, so this leaves out ticks spent in menus or paused
+        let simulated = Read::<Time>::get_data(&world).tick;
+        println!("Simulated {} ticks", simulated);
+        if simulated != ticks{
+            println!("Ran {} ticks of input, {} of them outside of play", ticks, ticks - simulated);
+        }
+        print_summary(&world);
+        return;
+    }
+
+    let mut gilrs = match gilrs::Gilrs::new(){
+        Ok(gilrs) => Some(gilrs),
+        Err(err) => {
+            eprintln!("Gamepads unavailable: {}", err);
+            None
+        },
+    };
 
     // Glium and Glutin setup
     let mut el = glutin::event_loop::EventLoop::new();
@@ -417,7 +2854,7 @@ fn main() {
     let wb = glutin::window::WindowBuilder::new()
         .with_title("SmolShooter")
         .with_inner_size(glutin::dpi::LogicalSize::new(640.0, 640.0))
-        .with_resizable(false);
+        .with_resizable(true);
 
     let windowed_context = glutin::ContextBuilder::new();
         //.build_windowed(wb, &el)
@@ -461,145 +2898,34 @@ fn main() {
     None
     ).unwrap();
 
-    // GET PLAYER TEXTURE
-    let image = image::load(Cursor::new(&include_bytes!("./assets/player.png")[..]),
-                        image::ImageFormat::Png).unwrap().to_rgba();
-    let image_dimensions = image.dimensions();
-    let image = glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    let player_tex = glium::texture::Texture2d::new(&renderer, image).unwrap();
-    
-    // GET ENEMY TEXTURE
-    let image = image::load(Cursor::new(&include_bytes!("./assets/enemy.png")[..]),
-                        image::ImageFormat::Png).unwrap().to_rgba();
-    let image_dimensions = image.dimensions();
-    let image = glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    let enemy_tex = glium::texture::Texture2d::new(&renderer, image).unwrap();
+    // Every sprite lives in one texture array so the whole scene is a single draw call
+    let sprite_textures = glium::texture::Texture2dArray::new(&renderer, vec![
+        load_sprite(include_bytes!("./assets/player.png")),
+        load_sprite(include_bytes!("./assets/enemy.png")),
+        load_sprite(include_bytes!("./assets/asteroid.png")),
+        load_sprite(include_bytes!("./assets/bullet.png")),
+    ]).unwrap();
+    let mut sprites = Vec::new();
 
-    // GET ASTEROID TEXTURE
-    let image = image::load(Cursor::new(&include_bytes!("./assets/asteroid.png")[..]),
-    image::ImageFormat::Png).unwrap().to_rgba();
-    let image_dimensions = image.dimensions();
-    let image = glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    let asteroid_tex = glium::texture::Texture2d::new(&renderer, image).unwrap();
+    // Text goes through the same quad and program, just with the font as its texture array
+    let font_textures = glium::texture::Texture2dArray::new(&renderer, build_font()).unwrap();
+    let mut glyphs = Vec::new();
 
-    
-    // GET BULLET TEXTURE
-    let image = image::load(Cursor::new(&include_bytes!("./assets/bullet.png")[..]),
-    image::ImageFormat::Png).unwrap().to_rgba();
-    let image_dimensions = image.dimensions();
-    let image = glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    let bullet_tex = glium::texture::Texture2d::new(&renderer, image).unwrap();
-    
-    let params = glium::DrawParameters{
+    let mut params = glium::DrawParameters{
         blend: glium::draw_parameters::Blend::alpha_blending(),
+        viewport: Some(arena_viewport(renderer.get_framebuffer_dimensions(), &Read::<WorldBounds>::get_data(&world))),
         .. Default::default()
     };
 
 
-    // SmolECS Setup
-    let mut world = World::new();
-    world.register_comp::<Player>();
-    world.register_comp::<Enemy>();
-    world.register_comp::<Velocity>();
-    world.register_comp::<Position>();
-    world.register_comp::<Radius>();
-    world.register_comp::<Health>();
-    world.register_comp::<Rotation>();
-    world.register_comp::<RotationVelocity>();
-    world.register_comp::<Asteroid>();
-    world.register_comp::<Bullet>();
-
-    world.insert(WorldBounds{x: 10.0, y: 10.0});
-    world.insert(Time{
-        beginning: std::time::Instant::now(),
-        last: std::time::Instant::now(),
-        total: 0.0,
-        delta: 0.0,
-    });
-    world.insert(ControlInputs{pressed: HashSet::new(), down: HashSet::new()});
-    world.insert(EntityStorage::new());
-    
-    let mut ents = Write::<EntityStorage>::get_data(&world);
-    let mut players = WriteComp::<Player>::get_data(&world);
-    let mut enemies = WriteComp::<Enemy>::get_data(&world);
-    let mut health = WriteComp::<Health>::get_data(&world);
-    let mut positions = WriteComp::<Position>::get_data(&world);
-    let mut vels = WriteComp::<Velocity>::get_data(&world);
-    let mut radius = WriteComp::<Radius>::get_data(&world);
-    let mut angles = WriteComp::<Rotation>::get_data(&world);
-    let mut angle_vel = WriteComp::<RotationVelocity>::get_data(&world);
-    let mut asteroids = WriteComp::<Asteroid>::get_data(&world);
-    
-    //Make the player
-    ents.create_entity()
-        .add(&mut players, Player{})
-        .add(&mut health, Health(5))
-        .add(&mut positions, Position{x: 0.0, y: -9.5})
-        .add(&mut vels, Velocity{x: 0.0, y: 0.0})
-        .add(&mut radius, Radius(0.5))
-        .add(&mut angles, Rotation(0.0))
-        .add(&mut angle_vel, RotationVelocity(0.0));
-
-        
-    //Make the enemies
-    ents.create_entity()
-        .add(&mut enemies, Enemy{})
-        .add(&mut health, Health(5))
-        .add(&mut positions, Position{x: 0.0, y: 9.5})
-        .add(&mut vels, Velocity{x: 0.0, y: 0.0})
-        .add(&mut radius, Radius(0.5))
-        .add(&mut angles, Rotation(180.0))
-        .add(&mut angle_vel, RotationVelocity(0.0));
-
-
-    let mut rng = rand::thread_rng();
-    for i in 0..30{
-        ents.create_entity()
-            .add(&mut health, Health(1))
-            .add(&mut positions, Position{x: rng.gen_range(-10.0, 10.0), y: rng.gen_range(-7.0, 7.0)})
-            .add(&mut vels, Velocity{x: rng.gen_range(-2.0, 2.0), y: rng.gen_range(-2.0, 2.0)})
-            .add(&mut radius, Radius(0.5))
-            .add(&mut angles, Rotation(rng.gen_range(0.0, 360.0)))
-            .add(&mut angle_vel, RotationVelocity(rng.gen_range(-90.0, 90.0)))
-            .add(&mut asteroids, Asteroid{});
-    }
-    
-    
-    let mut scheduler = SystemScheduler::new(Arc::new(ThreadPoolBuilder::new().num_threads(4).build().unwrap()));
-    scheduler.add(UpdateTime{}, "update_time", vec![]);
-    scheduler.add(ApplyControls{}, "apply_controls", vec!["update_time"]);
-    scheduler.add(ApplyVelocities{}, "update_positions", vec!["update_time", "apply_controls"]);
-    scheduler.add(ApplyRotationVelocities{}, "update_angles", vec!["update_time", "apply_controls"]);
-    scheduler.add(DamagerCollisionCheck{}, "damage_check", vec!["update_positions"]);
-    scheduler.add(DestroyZeroHealth{}, "destroy_zero", vec!["damage_check"]);
-
     let mut closed = false;
-
-    drop(ents);
-    drop(players);
-    drop(enemies);
-    drop(health);
-    drop(positions);
-    drop(vels);
-    drop(radius);
-    drop(angles);
-    drop(angle_vel);
-    drop(asteroids);
+    // Last match message shown in the window title
+    let mut title = String::new();
 
     // Main Loop
     loop{
         el.run_return(|event, _, control_flow| {
             *control_flow = ControlFlow::Poll;
-        
-            let mut keys = Write::<ControlInputs>::get_data(&world);
-            let mut new_down = HashSet::new();
-            for key in keys.pressed.drain(){
-                new_down.insert(key);
-            }
-
-            for key in new_down.drain(){
-                keys.down.insert(key);
-            }
 
             match event {
                 Event::WindowEvent {event: WindowEvent::CloseRequested, .. } => {
@@ -608,26 +2934,71 @@ fn main() {
                     closed = true;
                     return;
                 },
+                Event::WindowEvent {event: WindowEvent::Resized(size), .. } => {
+                    if size.width > 0 && size.height > 0{
+                        params.viewport = Some(arena_viewport((size.width, size.height), &Read::<WorldBounds>::get_data(&world)));
+                    }
+                    return;
+                },
                 Event::WindowEvent {event: WindowEvent::KeyboardInput{input: KeyboardInput{virtual_keycode, state, ..}, ..}, ..} =>{
+                    let mut keys = Write::<ControlInputs>::get_data(&world);
                     match virtual_keycode{
                         Some(key) => {
                             match state{
                                 glutin::event::ElementState::Pressed => {
-                                    keys.pressed.insert(key);
+                                    let repeat = !keys.key_pressed(key);
+                                    if key == VirtualKeyCode::F11 && !repeat{
+                                        let gl_window = renderer.gl_window();
+                                        let window = gl_window.window();
+                                        match window.fullscreen(){
+                                            Some(_) => window.set_fullscreen(None),
+                                            None => window.set_fullscreen(Some(glutin::window::Fullscreen::Borderless(window.current_monitor()))),
+                                        }
+                                    }
+                                    if key == VirtualKeyCode::F5 && !repeat && config_locked{
+                                        eprintln!("Config reloads are disabled while recording or replaying");
+                                    } else if key == VirtualKeyCode::F5 && !repeat{
+                                        match load_config(&options.config, humans){
+                                            Ok(config) => {
+                                                *Write::<Bindings>::get_data(&world) = config.controls;
+                                                Write::<Waves>::get_data(&world).curve = config.waves;
+                                                println!("Reloaded config from {}", options.config);
+                                            },
+                                            Err(err) => eprintln!("Keeping current config, {}", err),
+                                        }
+                                    }
                                 },
                                 glutin::event::ElementState::Released => {
-                                    keys.down.remove(&key);
+                                    keys.key_released(key);
                                 },
                             }
                         },
                         None => {},
                     }
-                },  
-                _ => {}
+                    return;
+                },
+                Event::MainEventsCleared => {},
+                _ => {return;}
+            }
+
+            if let Some(gilrs) = gilrs.as_mut(){
+                // Gamepad state only updates as its events are pulled off the queue
+                while gilrs.next_event().is_some(){}
+                Write::<GamepadInputs>::get_data(&world).sample(gilrs);
+            }
+
+            // Run however many fixed ticks have built up since the last frame
+            let ticks = Write::<Time>::get_data(&world).advance_frame();
+            for _ in 0..ticks{
+                run_tick();
             }
-            drop(keys);
 
-            scheduler.run(&world);
+            let game = Read::<Match>::get_data(&world);
+            let message = game.message();
+            if message != title{
+                renderer.gl_window().window().set_title(&format!("SmolShooter - {}", message));
+                title = message;
+            }
             
             let mut frame = renderer.draw();
             frame.clear_color(0.0, 0.0, 0.0, 0.0);
@@ -638,86 +3009,77 @@ fn main() {
             let positions = ReadComp::<Position>::get_data(&world);
             let asteroids = ReadComp::<Asteroid>::get_data(&world);
             let bullets = ReadComp::<Bullet>::get_data(&world);
-
-
-            for (asteroid, position, angle) in (&asteroids, &positions, &angles).join(){
-                let uniform = uniform! {
-                    p: [
-                        [0.1, 0.0, 0.0, 0.0],
-                        [0.0, 0.1, 0.0, 0.0],
-                        [0.0, 0.0, -0.1, 0.0],
-                        [0.0, 0.0, 0.0, 1.0_f32],
-                    ],
-                    pos: [position.x, position.y],
-                    rots: [(angle.0/180.0 * std::f32::consts::PI).sin(), (angle.0/180.0 * std::f32::consts::PI).cos()],
-                    tex: &asteroid_tex
-                };
-                frame.draw(
-                    &vertex_buffer, 
-                    &indicies,
-                        &program, 
-                    &uniform,
-                    &params).unwrap();
+            let vels = ReadComp::<Velocity>::get_data(&world);
+            let radii = ReadComp::<Radius>::get_data(&world);
+            let invulnerables = ReadComp::<Invulnerable>::get_data(&world);
+            let ents = Read::<EntityStorage>::get_data(&world);
+            let time = Read::<Time>::get_data(&world);
+            let bounds = Read::<WorldBounds>::get_data(&world);
+            let projection = bounds.projection();
+            // Nothing moves outside of play, so nothing is extrapolated
+            let ahead = if game.state == GameState::Playing {(time.alpha * time.delta) as f32} else {0.0};
+
+            sprites.clear();
+            for (asteroid, position, vel, angle, radius) in (&asteroids, &positions, &vels, &angles, &radii).join(){
+                push_wrapped(&mut sprites, SpriteInstance::new(Sprite::Asteroid, render_position(position, vel, ahead), rotation_vector(angle)).scaled(radius.0 / ASTEROID_RADIUS), &bounds);
             }
-
-            for (player, position, angle) in (&players, &positions, &angles).join(){
-                let uniform = uniform! {
-                    p: [
-                        [0.1, 0.0, 0.0, 0.0],
-                        [0.0, 0.1, 0.0, 0.0],
-                        [0.0, 0.0, -0.1, 0.0],
-                        [0.0, 0.0, 0.0, 1.0_f32],
-                    ],
-                    pos: [position.x, position.y],
-                    rots: [(angle.0/180.0 * std::f32::consts::PI).sin(), (angle.0/180.0 * std::f32::consts::PI).cos()],
-                    tex: &player_tex
-                };
-                frame.draw(
-                    &vertex_buffer, 
-                    &indicies,
-                        &program, 
-                    &uniform,
-                    &params).unwrap();
+            let mut blinked_out = Vec::new();
+            for (invulnerable, entity) in (&invulnerables, ents.deref()).join(){
+                if (invulnerable.seconds * BLINK_RATE) as u32 % 2 == 1{
+                    blinked_out.push(entity.clone());
+                }
+            }
+            for (player, position, vel, angle, entity) in (&players, &positions, &vels, &angles, ents.deref()).join(){
+                if blinked_out.contains(entity){
+                    continue;
+                }
+                push_wrapped(&mut sprites, SpriteInstance::new(Sprite::Player, render_position(position, vel, ahead), rotation_vector(angle)), &bounds);
+            }
+            for (enemy, position, vel, angle, entity) in (&enemies, &positions, &vels, &angles, ents.deref()).join(){
+                if blinked_out.contains(entity){
+                    continue;
+                }
+                push_wrapped(&mut sprites, SpriteInstance::new(Sprite::Enemy, render_position(position, vel, ahead), rotation_vector(angle)), &bounds);
+            }
+            for (bullet, position, vel) in (&bullets, &positions, &vels).join(){
+                push_wrapped(&mut sprites, SpriteInstance::new(Sprite::Bullet, render_position(position, vel, ahead), [1.0, 1.0]), &bounds);
             }
 
-            for (enemy, position, angle) in (&enemies, &positions, &angles).join(){
+            if !sprites.is_empty(){
+                let instance_buffer = VertexBuffer::dynamic(&renderer, &sprites).unwrap();
                 let uniform = uniform! {
-                    p: [
-                        [0.1, 0.0, 0.0, 0.0],
-                        [0.0, 0.1, 0.0, 0.0],
-                        [0.0, 0.0, -0.1, 0.0],
-                        [0.0, 0.0, 0.0, 1.0_f32],
-                    ],
-                    pos: [position.x, position.y],
-                    rots: [(angle.0/180.0 * std::f32::consts::PI).sin(), (angle.0/180.0 * std::f32::consts::PI).cos()],
-                    tex: &enemy_tex
+                    p: projection,
+                    tex: &sprite_textures
                 };
                 frame.draw(
-                    &vertex_buffer, 
+                    (&vertex_buffer, instance_buffer.per_instance().unwrap()),
                     &indicies,
-                        &program, 
+                    &program,
                     &uniform,
                     &params).unwrap();
             }
 
-            for (bullet, position) in (&bullets, &positions).join(){
+            glyphs.clear();
+            build_hud(&world, &mut glyphs, frame.get_dimensions());
+            if !glyphs.is_empty(){
+                let instance_buffer = VertexBuffer::dynamic(&renderer, &glyphs).unwrap();
                 let uniform = uniform! {
-                    p: [
-                        [0.1, 0.0, 0.0, 0.0],
-                        [0.0, 0.1, 0.0, 0.0],
-                        [0.0, 0.0, -0.1, 0.0],
-                        [0.0, 0.0, 0.0, 1.0_f32],
-                    ],
-                    pos: [position.x, position.y],
-                    rots: [1.0 as f32, 1.0  as f32],
-                    tex: &bullet_tex
+                    p: hud_projection(frame.get_dimensions()),
+                    tex: font_textures.sampled()
+                        .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest)
+                        .minify_filter(glium::uniforms::MinifySamplerFilter::Nearest)
+                };
+                // The HUD covers the whole window, letterbox bars included
+                let hud_params = glium::DrawParameters{
+                    viewport: None,
+                    .. params.clone()
                 };
                 frame.draw(
-                    &vertex_buffer, 
+                    (&vertex_buffer, instance_buffer.per_instance().unwrap()),
                     &indicies,
-                        &program, 
+                    &program,
                     &uniform,
-                    &params).unwrap();
+                    &hud_params).unwrap();
             }
 
             drop(players);
@@ -726,6 +3088,12 @@ fn main() {
             drop(bullets);
             drop(positions);
             drop(asteroids);
+            drop(vels);
+            drop(time);
+            drop(bounds);
+            drop(game);
+            drop(invulnerables);
+            drop(ents);
 
             frame.finish();
         });
@@ -734,3 +3102,192 @@ fn main() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn held_key_goes_pressed_down_released_up(){
+        let mut inputs = ControlInputs::new();
+        assert!(inputs.key_pressed(VirtualKeyCode::W));
+
+        inputs.sample();
+        assert_eq!(inputs.key_status(VirtualKeyCode::W), KeyStatus::Pressed);
+        inputs.sample();
+        assert_eq!(inputs.key_status(VirtualKeyCode::W), KeyStatus::Down);
+        inputs.sample();
+        assert_eq!(inputs.key_status(VirtualKeyCode::W), KeyStatus::Down);
+
+        inputs.key_released(VirtualKeyCode::W);
+        inputs.sample();
+        assert_eq!(inputs.key_status(VirtualKeyCode::W), KeyStatus::Released);
+        inputs.sample();
+        assert_eq!(inputs.key_status(VirtualKeyCode::W), KeyStatus::Up);
+    }
+
+    #[test]
+    fn press_edge_survives_events_between_ticks(){
+        let mut inputs = ControlInputs::new();
+        inputs.key_pressed(VirtualKeyCode::S);
+        // Unrelated events arriving before the tick must not eat the edge
+        inputs.key_pressed(VirtualKeyCode::A);
+        inputs.key_released(VirtualKeyCode::A);
+
+        inputs.sample();
+        assert_eq!(inputs.key_status(VirtualKeyCode::S), KeyStatus::Pressed);
+    }
+
+    #[test]
+    fn tap_between_ticks_is_seen_for_one_tick(){
+        let mut inputs = ControlInputs::new();
+        inputs.key_pressed(VirtualKeyCode::S);
+        inputs.key_released(VirtualKeyCode::S);
+
+        inputs.sample();
+        assert_eq!(inputs.key_status(VirtualKeyCode::S), KeyStatus::Pressed);
+        inputs.sample();
+        assert_eq!(inputs.key_status(VirtualKeyCode::S), KeyStatus::Released);
+        inputs.sample();
+        assert_eq!(inputs.key_status(VirtualKeyCode::S), KeyStatus::Up);
+    }
+
+    #[test]
+    fn key_repeat_is_ignored(){
+        let mut inputs = ControlInputs::new();
+        assert!(inputs.key_pressed(VirtualKeyCode::Down));
+        inputs.sample();
+
+        assert!(!inputs.key_pressed(VirtualKeyCode::Down));
+        inputs.sample();
+        assert_eq!(inputs.key_status(VirtualKeyCode::Down), KeyStatus::Down);
+    }
+
+    #[test]
+    fn untouched_key_is_up(){
+        let mut inputs = ControlInputs::new();
+        inputs.sample();
+        assert_eq!(inputs.key_status(VirtualKeyCode::Up), KeyStatus::Up);
+        assert!(!inputs.key_status(VirtualKeyCode::Up).is_held());
+    }
+
+    #[test]
+    fn replayed_keys_produce_the_same_transitions(){
+        let mut live = ControlInputs::new();
+        let mut replayed = ControlInputs::new();
+
+        live.key_pressed(VirtualKeyCode::W);
+        live.sample();
+        replayed.advance(live.active());
+        assert_eq!(replayed.key_status(VirtualKeyCode::W), live.key_status(VirtualKeyCode::W));
+
+        live.key_released(VirtualKeyCode::W);
+        live.sample();
+        replayed.advance(live.active());
+        assert_eq!(replayed.key_status(VirtualKeyCode::W), live.key_status(VirtualKeyCode::W));
+    }
+
+    // Bit patterns of every position and health, in join order
+    fn snapshot(world: &World) -> Vec<(u32, u32, isize)>{
+        let positions = ReadComp::<Position>::get_data(world);
+        let healths = ReadComp::<Health>::get_data(world);
+        (&positions, &healths).join()
+            .map(|(position, health)| (position.x.to_bits(), position.y.to_bits(), health.0))
+            .collect()
+    }
+
+    fn header(seed: u64) -> RecordingHeader{
+        RecordingHeader{
+            version: RECORDING_VERSION,
+            seed,
+            skip_title: true,
+            arena: (24.0, 16.0),
+            enemy_source: ControlSource::Human(1),
+            controls: Bindings::default(),
+            waves: WaveCurve::default(),
+        }
+    }
+
+    // Sets up the match a recording with this header was made from, the same way main does
+    fn world_for(header: &RecordingHeader) -> World{
+        let config = Config{controls: header.controls.clone(), waves: header.waves.clone()};
+        let world = setup_world(header.seed, WorldBounds::new(header.arena.0 / 2.0, header.arena.1 / 2.0), config, header.enemy_source);
+        if header.skip_title{
+            start_round(&world);
+        }
+        world
+    }
+
+    fn simulate(seed: u64, ticks: u64) -> Vec<(u32, u32, isize)>{
+        let world = world_for(&header(seed));
+        let scheduler = build_scheduler!();
+        for tick in 0..ticks{
+            {
+                let mut inputs = Write::<ControlInputs>::get_data(&world);
+                inputs.key_pressed(VirtualKeyCode::W);
+                match tick % 20{
+                    0 => {inputs.key_pressed(VirtualKeyCode::S);},
+                    1 => inputs.key_released(VirtualKeyCode::S),
+                    _ => {},
+                }
+            }
+            simulate_tick(&world, &mut None, &mut None, || scheduler.run(&world));
+        }
+        snapshot(&world)
+    }
+
+    #[test]
+    fn same_seed_and_inputs_give_identical_state(){
+        assert_eq!(simulate(42, 300), simulate(42, 300));
+    }
+
+    // Plays a recording to the end through the same tick loop as a headless run
+    fn play_back(path: &str) -> Vec<(u32, u32, isize)>{
+        let mut replay = Some(InputReplay::open(path).unwrap());
+        let world = world_for(&replay.as_ref().unwrap().header);
+        let scheduler = build_scheduler!();
+        let mut recorder = None;
+        while simulate_tick(&world, &mut replay, &mut recorder, || scheduler.run(&world)){}
+        snapshot(&world)
+    }
+
+    #[test]
+    fn replaying_a_recording_reproduces_the_match(){
+        let path = std::env::temp_dir().join(format!("smolshooter-{}.rec", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let header = header(7);
+        let world = world_for(&header);
+        let scheduler = build_scheduler!();
+        let mut replay = None;
+        let mut recorder = Some(InputRecorder::create(path, &header).unwrap());
+        for tick in 0..300{
+            {
+                let mut inputs = Write::<ControlInputs>::get_data(&world);
+                inputs.key_pressed(VirtualKeyCode::Up);
+                match tick % 15{
+                    0 => {inputs.key_pressed(VirtualKeyCode::Down);},
+                    1 => inputs.key_released(VirtualKeyCode::Down),
+                    _ => {},
+                }
+            }
+            simulate_tick(&world, &mut replay, &mut recorder, || scheduler.run(&world));
+        }
+        drop(recorder);
+        let recorded = snapshot(&world);
+
+        let first = play_back(path);
+        let second = play_back(path);
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(first, recorded);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn rng_stream_is_stable(){
+        // ChaCha8 promises this stream across releases, so a recorded seed keeps meaning the same match
+        let mut rng = GameRng::new(0);
+        let drawn: Vec<u32> = (0..4).map(|_| rng.gen()).collect();
+        assert_eq!(drawn, vec![2811902828, 3045455719, 3134767159, 2001118559]);
+    }
+}
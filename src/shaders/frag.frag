@@ -1,10 +1,11 @@
 #version 140
 
 in vec2 v_uv;
-uniform sampler2D tex;
+flat in float v_layer;
+uniform sampler2DArray tex;
 
 out vec4 color;
 
 void main() {
-    color = texture(tex, v_uv);
+    color = texture(tex, vec3(v_uv, v_layer));
 }
\ No newline at end of file
@@ -3,16 +3,20 @@
 in vec2 position;
 in vec2 uv;
 
+in vec2 offset;
+in vec2 rots;
+in float layer;
+in float scale;
+
 out vec2 v_uv;
+flat out float v_layer;
 
 uniform mat4 p;
-uniform vec2 pos;
-uniform vec2 rots;
 
 void main() {
     mat4 trans;
-    trans[0] = vec4(1, 0, 0, pos.x);
-    trans[1] = vec4(0, 1, 0, pos.y);
+    trans[0] = vec4(1, 0, 0, offset.x);
+    trans[1] = vec4(0, 1, 0, offset.y);
     trans[2] = vec4(0, 0, 1, 0);
     trans[3] = vec4(0, 0, 0, 1);
     trans = transpose(trans);
@@ -24,6 +28,7 @@ void main() {
     rot[3] = vec4(0, 0, 0, 1);
     rot = transpose(rot);
 
-    gl_Position = p * trans * rot * vec4(position, 0.0, 1.0);
+    gl_Position = p * trans * rot * vec4(position * scale, 0.0, 1.0);
     v_uv = uv;
+    v_layer = layer;
 }
\ No newline at end of file